@@ -0,0 +1,213 @@
+use aws_smithy_types::date_time::Format;
+use aws_smithy_types::DateTime;
+use aws_types::credentials::Credentials;
+use serde_json::{json, Value};
+
+use std::convert::TryFrom;
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// Cached credentials are not reused when they expire within this window
+const EXPIRY_BUFFER: Duration = Duration::from_secs(5 * 60);
+
+/// On disk cache of assumed role credentials. Entries are written in the same shape as the
+/// AWS CLI uses for `~/.aws/cli/cache`, so that the two can share a cache directory.
+#[derive(Debug, Clone)]
+pub struct CredentialCache {
+    dir: PathBuf,
+}
+
+impl CredentialCache {
+    /// A cache in `~/.aws/cli/cache`, if a home directory can be found
+    pub fn new() -> Option<Self> {
+        std::env::var_os("HOME")
+            .or_else(|| std::env::var_os("USERPROFILE"))
+            .map(|home| CredentialCache::with_dir(PathBuf::from(home).join(".aws/cli/cache")))
+    }
+
+    pub fn with_dir(dir: impl Into<PathBuf>) -> Self {
+        CredentialCache { dir: dir.into() }
+    }
+
+    fn path(&self, profile: &str, role: &str) -> PathBuf {
+        let key = format!("envfmt-{}-{}", profile, role)
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+
+        self.dir.join(key + ".json")
+    }
+
+    /// Loads credentials for a profile and role, if they are cached and not close to expiring
+    pub fn load(&self, profile: &str, role: &str) -> Option<Credentials> {
+        let contents = fs::read_to_string(self.path(profile, role)).ok()?;
+        let entry = serde_json::from_str::<Value>(&contents).ok()?;
+        let creds = entry.get("Credentials")?;
+
+        let expiration = creds.get("Expiration")?.as_str()?;
+        let expiration =
+            SystemTime::try_from(DateTime::from_str(expiration, Format::DateTime).ok()?).ok()?;
+
+        if expiration < SystemTime::now() + EXPIRY_BUFFER {
+            return None;
+        }
+
+        Some(Credentials::new(
+            creds.get("AccessKeyId")?.as_str()?,
+            creds.get("SecretAccessKey")?.as_str()?,
+            creds
+                .get("SessionToken")
+                .and_then(|t| t.as_str())
+                .map(|t| t.to_string()),
+            Some(expiration),
+            "AssumeRoleWithMFATokenCache",
+        ))
+    }
+
+    /// Stores credentials for a profile and role. Credentials without an expiration are not
+    /// cached.
+    pub fn store(&self, profile: &str, role: &str, credentials: &Credentials) -> io::Result<()> {
+        let expiration = match credentials.expiry() {
+            Some(expiry) => DateTime::from(expiry)
+                .fmt(Format::DateTime)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            None => return Ok(()),
+        };
+
+        let entry = json!({
+            "Credentials": {
+                "AccessKeyId": credentials.access_key_id(),
+                "SecretAccessKey": credentials.secret_access_key(),
+                "SessionToken": credentials.session_token(),
+                "Expiration": expiration,
+            }
+        });
+
+        let mut builder = DirBuilder::new();
+        builder.recursive(true);
+
+        #[cfg(unix)]
+        builder.mode(0o700);
+
+        builder.create(&self.dir)?;
+
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+
+        #[cfg(unix)]
+        options.mode(0o600);
+
+        let mut file = options.open(self.path(profile, role))?;
+
+        // The mode only applies to new files, so an existing entry is locked down as well
+        #[cfg(unix)]
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+
+        file.write_all(entry.to_string().as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(name: &str) -> CredentialCache {
+        let dir =
+            std::env::temp_dir().join(format!("envfmt-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        CredentialCache::with_dir(dir)
+    }
+
+    fn credentials(expires_in: Duration) -> Credentials {
+        Credentials::new(
+            "key",
+            "secret",
+            Some("token".to_string()),
+            Some(SystemTime::now() + expires_in),
+            "test",
+        )
+    }
+
+    #[test]
+    fn test_round_trips_credentials() {
+        let cache = cache("round-trip");
+        let role = "arn:aws:iam::123456789012:role/admin";
+
+        cache
+            .store("default", role, &credentials(Duration::from_secs(3600)))
+            .unwrap();
+
+        let loaded = cache.load("default", role).unwrap();
+
+        assert_eq!("key", loaded.access_key_id());
+        assert_eq!("secret", loaded.secret_access_key());
+        assert_eq!(Some("token"), loaded.session_token());
+        assert!(cache.load("other", role).is_none());
+    }
+
+    #[test]
+    fn test_ignores_credentials_close_to_expiring() {
+        let cache = cache("expiring");
+
+        cache
+            .store("default", "role", &credentials(Duration::from_secs(60)))
+            .unwrap();
+
+        assert!(cache.load("default", "role").is_none());
+    }
+
+    #[test]
+    fn test_writes_aws_cli_shape() {
+        let cache = cache("shape");
+
+        cache
+            .store("default", "role", &credentials(Duration::from_secs(3600)))
+            .unwrap();
+
+        let path = cache.path("default", "role");
+        let entry = serde_json::from_str::<Value>(&fs::read_to_string(&path).unwrap()).unwrap();
+
+        assert!(entry["Credentials"]["Expiration"]
+            .as_str()
+            .unwrap()
+            .ends_with('Z'));
+
+        #[cfg(unix)]
+        {
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(0o600, mode & 0o777);
+
+            let mode = fs::metadata(&cache.dir).unwrap().permissions().mode();
+            assert_eq!(0o700, mode & 0o777);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_restricts_existing_entries() {
+        let cache = cache("existing");
+        let path = cache.path("default", "role");
+
+        fs::create_dir_all(&cache.dir).unwrap();
+        fs::write(&path, "{}").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        cache
+            .store("default", "role", &credentials(Duration::from_secs(3600)))
+            .unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(0o600, mode & 0o777);
+    }
+}
@@ -0,0 +1,139 @@
+use regex::Regex;
+
+use crate::params::ParamBag;
+
+/// Client side filter applied to the keys of a `ParamBag` after the parameters have been
+/// fetched. Patterns are globs by default (`*` and `?`), or full regular expressions when the
+/// filter is built with `regex` enabled. Regular expressions are unanchored.
+#[derive(Debug, Default)]
+pub struct KeyFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl KeyFilter {
+    pub fn new(include: &[String], exclude: &[String], regex: bool) -> Result<Self, regex::Error> {
+        let compile = |patterns: &[String]| -> Result<Vec<Regex>, regex::Error> {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    if regex {
+                        Regex::new(pattern)
+                    } else {
+                        Regex::new(&glob_to_regex(pattern))
+                    }
+                })
+                .collect()
+        };
+
+        Ok(KeyFilter {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// A key matches when it matches at least one include pattern (or there are none) and
+    /// does not match any exclude pattern
+    pub fn matches(&self, key: &str) -> bool {
+        let included = self.include.is_empty() || self.include.iter().any(|re| re.is_match(key));
+        let excluded = self.exclude.iter().any(|re| re.is_match(key));
+
+        included && !excluded
+    }
+
+    pub fn apply(&self, bag: &mut ParamBag) {
+        if !self.is_empty() {
+            bag.params.retain(|param| self.matches(&param.key));
+        }
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut out = String::from("^");
+
+    for c in glob.chars() {
+        match c {
+            '*' => out.push_str(".*"),
+            '?' => out.push('.'),
+            _ => out.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    out.push('$');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::tests;
+
+    fn bag() -> ParamBag {
+        tests::bag(&[
+            ("DB_HOST", "value"),
+            ("DB_PASS", "value"),
+            ("API_KEY", "value"),
+            ("OTHER_SERVICE_KEY", "value"),
+        ])
+    }
+
+    fn keys(bag: &ParamBag) -> Vec<&str> {
+        bag.params.iter().map(|p| p.key.as_str()).collect()
+    }
+
+    #[test]
+    fn test_empty_filter_keeps_everything() {
+        let mut bag = bag();
+        KeyFilter::default().apply(&mut bag);
+
+        assert_eq!(4, bag.params.len());
+    }
+
+    #[test]
+    fn test_include_globs() {
+        let mut bag = bag();
+        KeyFilter::new(&["DB_*".to_string()], &[], false)
+            .unwrap()
+            .apply(&mut bag);
+
+        assert_eq!(vec!["DB_HOST", "DB_PASS"], keys(&bag));
+    }
+
+    #[test]
+    fn test_exclude_wins_over_include() {
+        let mut bag = bag();
+        KeyFilter::new(&["*_KEY".to_string()], &["OTHER_*".to_string()], false)
+            .unwrap()
+            .apply(&mut bag);
+
+        assert_eq!(vec!["API_KEY"], keys(&bag));
+    }
+
+    #[test]
+    fn test_globs_are_anchored_and_escaped() {
+        let filter = KeyFilter::new(&["DB.HOS?".to_string()], &[], false).unwrap();
+
+        assert!(filter.matches("DB.HOST"));
+        assert!(!filter.matches("DB_HOST"));
+        assert!(!filter.matches("MY_DB.HOST"));
+    }
+
+    #[test]
+    fn test_regex_patterns() {
+        let mut bag = bag();
+        KeyFilter::new(&["^(DB|API)_".to_string()], &["PASS$".to_string()], true)
+            .unwrap()
+            .apply(&mut bag);
+
+        assert_eq!(vec!["DB_HOST", "API_KEY"], keys(&bag));
+    }
+
+    #[test]
+    fn test_invalid_regex_is_an_error() {
+        assert!(KeyFilter::new(&["(".to_string()], &[], true).is_err());
+    }
+}
@@ -1,43 +1,121 @@
+use serde_json::{json, Value};
+
 use std::fmt;
 
+use crate::opt::Format;
 use crate::params::{Param, ParamBag};
+use crate::transform::ListStyle;
+
+impl Format {
+    /// Formats the parameters of a bag for printing, optionally preceded by comments with the
+    /// metadata of each parameter. JSON has no comments, so it leaves the metadata out. `lists`
+    /// is the style the `StringList` values of the bag were given, and JSON and YAML output
+    /// values given as JSON arrays as arrays.
+    pub fn format<'a>(
+        &self,
+        bag: &'a ParamBag,
+        metadata: bool,
+        lists: ListStyle,
+    ) -> Box<dyn fmt::Display + 'a> {
+        let arrays = lists == ListStyle::Json;
+
+        match self {
+            Format::DotEnv => Box::new(DotEnv::from(bag).with_metadata(metadata)),
+            Format::PhpFpm => Box::new(PhpFpm::from(bag).with_metadata(metadata)),
+            Format::Json => Box::new(Json::from(bag).with_arrays(arrays)),
+            Format::Yaml => Box::new(Yaml::from(bag).with_metadata(metadata).with_arrays(arrays)),
+        }
+    }
+}
 
 pub struct DotEnv<'a> {
     params: &'a Vec<Param>,
+    metadata: bool,
 }
 
 impl<'a> From<&'a ParamBag> for DotEnv<'a> {
     fn from(bag: &'a ParamBag) -> Self {
         DotEnv {
             params: &bag.params,
+            metadata: false,
         }
     }
 }
 
+impl<'a> DotEnv<'a> {
+    pub fn with_metadata(mut self, metadata: bool) -> Self {
+        self.metadata = metadata;
+        self
+    }
+}
+
 impl<'a> fmt::Display for DotEnv<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let out = self
             .params
             .iter()
-            .map(|param: &Param| param.key.clone() + "=" + "\"" + &param.value + "\"\n")
+            .map(|param: &Param| {
+                comment(param, "#", self.metadata)
+                    + &param.key
+                    + "="
+                    + "\""
+                    + &escape(param.value.expose())
+                    + "\"\n"
+            })
             .collect::<String>();
 
         write!(f, "{}", out.trim())
     }
 }
 
+/// Escapes backslashes, double quotes and carriage returns so that values survive being read
+/// back. Newlines are kept as is, giving multi-line quoted values.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\r', "\\r")
+}
+
+/// Escapes backslashes, double quotes and dollar signs, which are the only escapes PHP's INI
+/// parser knows. Escaping `$` keeps `${VAR}` from being interpolated. Other characters,
+/// carriage returns included, are written as is.
+fn escape_ini(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('$', "\\$")
+}
+
+/// A comment line describing where a parameter came from, if asked for and known
+fn comment(param: &Param, marker: &str, metadata: bool) -> String {
+    match &param.meta {
+        Some(meta) if metadata => format!("{} {}\n", marker, meta),
+        _ => String::new(),
+    }
+}
+
 pub struct PhpFpm<'a> {
     params: &'a Vec<Param>,
+    metadata: bool,
 }
 
 impl<'a> From<&'a ParamBag> for PhpFpm<'a> {
     fn from(bag: &'a ParamBag) -> Self {
         PhpFpm {
             params: &bag.params,
+            metadata: false,
         }
     }
 }
 
+impl<'a> PhpFpm<'a> {
+    pub fn with_metadata(mut self, metadata: bool) -> Self {
+        self.metadata = metadata;
+        self
+    }
+}
+
 impl<'a> fmt::Display for PhpFpm<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let prefix = "env[";
@@ -46,7 +124,13 @@ impl<'a> fmt::Display for PhpFpm<'a> {
             .params
             .iter()
             .map(|param: &Param| {
-                prefix.to_string() + &param.key + "]=" + "\"" + &param.value + "\"\n"
+                comment(param, ";", self.metadata)
+                    + prefix
+                    + &param.key
+                    + "]="
+                    + "\""
+                    + &escape_ini(param.value.expose())
+                    + "\"\n"
             })
             .collect::<String>();
 
@@ -54,34 +138,166 @@ impl<'a> fmt::Display for PhpFpm<'a> {
     }
 }
 
+/// The value of a parameter for JSON and YAML output, as an array for `StringList` values that
+/// `--string-lists json` turned into JSON arrays
+fn structured(param: &Param, arrays: bool) -> Value {
+    let value = param.value.expose();
+
+    if arrays && param.is_list() {
+        if let Ok(items) = serde_json::from_str::<Vec<String>>(value) {
+            return Value::from(items);
+        }
+    }
+
+    Value::from(value)
+}
+
+/// A flat JSON object of keys and values
+pub struct Json<'a> {
+    params: &'a Vec<Param>,
+    arrays: bool,
+}
+
+impl<'a> From<&'a ParamBag> for Json<'a> {
+    fn from(bag: &'a ParamBag) -> Self {
+        Json {
+            params: &bag.params,
+            arrays: false,
+        }
+    }
+}
+
+impl<'a> Json<'a> {
+    pub fn with_arrays(mut self, arrays: bool) -> Self {
+        self.arrays = arrays;
+        self
+    }
+}
+
+impl<'a> fmt::Display for Json<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.params.is_empty() {
+            return write!(f, "{{}}");
+        }
+
+        let out = self
+            .params
+            .iter()
+            .map(|param: &Param| {
+                format!(
+                    "  {}: {}",
+                    Value::from(param.key.as_str()),
+                    structured(param, self.arrays)
+                )
+            })
+            .collect::<Vec<_>>();
+
+        write!(f, "{{\n{}\n}}", out.join(",\n"))
+    }
+}
+
+/// A flat YAML map of keys and values, with values quoted wherever YAML would otherwise read
+/// them as something other than a string
+pub struct Yaml<'a> {
+    params: &'a Vec<Param>,
+    metadata: bool,
+    arrays: bool,
+}
+
+impl<'a> From<&'a ParamBag> for Yaml<'a> {
+    fn from(bag: &'a ParamBag) -> Self {
+        Yaml {
+            params: &bag.params,
+            metadata: false,
+            arrays: false,
+        }
+    }
+}
+
+impl<'a> Yaml<'a> {
+    pub fn with_metadata(mut self, metadata: bool) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn with_arrays(mut self, arrays: bool) -> Self {
+        self.arrays = arrays;
+        self
+    }
+}
+
+impl<'a> fmt::Display for Yaml<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let out = self
+            .params
+            .iter()
+            .map(|param: &Param| {
+                let entry = json!({ param.key.as_str(): structured(param, self.arrays) });
+
+                serde_yaml_ng::to_string(&entry)
+                    .map(|entry| comment(param, "#", self.metadata) + &entry)
+                    .map_err(|_| fmt::Error)
+            })
+            .collect::<Result<String, _>>()?;
+
+        // Not trimmed, as the line breaks ending a block scalar may be part of its value
+        write!(f, "{}", out)
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use aws_sdk_ssm::model::ParameterType;
+    use proptest::prelude::*;
+
     use super::*;
+    use crate::params::tests::{bag, meta};
+    use crate::params::ParamMeta;
+    use crate::parser::{InputFormat, ParseError, Parsed};
+
+    const FORMATS: [Format; 4] = [Format::DotEnv, Format::PhpFpm, Format::Json, Format::Yaml];
+
+    fn round_trip(format: &Format, bag: &ParamBag) -> Parsed {
+        InputFormat::from(format).parse(&format.format(bag, false, ListStyle::Join).to_string())
+    }
 
     #[test]
     fn formats_as_dot_env() {
         let params = vec![
             Param {
                 key: "ALPHA".to_string(),
-                value: "the".to_string(),
+                value: "the".into(),
+                meta: None,
             },
             Param {
                 key: "BETA".to_string(),
-                value: "four".to_string(),
+                value: "four".into(),
+                meta: None,
             },
             Param {
                 key: "DELTA".to_string(),
-                value: "test".to_string(),
+                value: "test".into(),
+                meta: None,
             },
             Param {
                 key: "GAMMA".to_string(),
-                value: "strings".to_string(),
+                value: "strings".into(),
+                meta: None,
             },
         ];
 
         let output = "ALPHA=\"the\"\nBETA=\"four\"\nDELTA=\"test\"\nGAMMA=\"strings\"";
 
-        assert_eq!(output, format!("{}", DotEnv { params: &params }));
+        assert_eq!(
+            output,
+            format!(
+                "{}",
+                DotEnv {
+                    params: &params,
+                    metadata: false
+                }
+            )
+        );
     }
 
     #[test]
@@ -89,25 +305,170 @@ mod tests {
         let params = vec![
             Param {
                 key: "ALPHA".to_string(),
-                value: "the".to_string(),
+                value: "the".into(),
+                meta: None,
             },
             Param {
                 key: "BETA".to_string(),
-                value: "four".to_string(),
+                value: "four".into(),
+                meta: None,
             },
             Param {
                 key: "DELTA".to_string(),
-                value: "test".to_string(),
+                value: "test".into(),
+                meta: None,
             },
             Param {
                 key: "GAMMA".to_string(),
-                value: "strings".to_string(),
+                value: "strings".into(),
+                meta: None,
             },
         ];
 
         let output =
             "env[ALPHA]=\"the\"\nenv[BETA]=\"four\"\nenv[DELTA]=\"test\"\nenv[GAMMA]=\"strings\"";
 
-        assert_eq!(output, format!("{}", PhpFpm { params: &params }));
+        assert_eq!(
+            output,
+            format!(
+                "{}",
+                PhpFpm {
+                    params: &params,
+                    metadata: false
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_escapes_php_fpm_values() {
+        let bag = bag(&[("WIN", "C:\\new\r"), ("VARS", "$HOME ${HOME} \"x\"")]);
+
+        assert_eq!(
+            "env[WIN]=\"C:\\\\new\r\"\nenv[VARS]=\"\\$HOME \\${HOME} \\\"x\\\"\"",
+            Format::PhpFpm
+                .format(&bag, false, ListStyle::Join)
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_outputs_string_lists_as_arrays() {
+        let mut bag = bag(&[("HOSTS", "a,b"), ("NAME", "[\"x\"]")]);
+        bag.params[0].meta = Some(ParamMeta {
+            param_type: Some(ParameterType::StringList),
+            ..meta("/app/hosts")
+        });
+
+        assert_eq!(
+            "{\n  \"HOSTS\": \"a,b\",\n  \"NAME\": \"[\\\"x\\\"]\"\n}",
+            Format::Json
+                .format(&bag, false, ListStyle::Join)
+                .to_string()
+        );
+
+        ListStyle::Json.apply(&mut bag).unwrap();
+
+        assert_eq!(
+            "{\n  \"HOSTS\": [\"a\",\"b\"],\n  \"NAME\": \"[\\\"x\\\"]\"\n}",
+            Format::Json
+                .format(&bag, false, ListStyle::Json)
+                .to_string()
+        );
+        assert_eq!(
+            "HOSTS:\n- a\n- b\nNAME: '[\"x\"]'\n",
+            Format::Yaml
+                .format(&bag, false, ListStyle::Json)
+                .to_string()
+        );
+        assert_eq!(
+            "{}",
+            Format::Json
+                .format(&ParamBag::new("/app"), false, ListStyle::Join)
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_round_trips_tricky_values() {
+        let bag = bag(&[
+            ("EMPTY", ""),
+            ("QUOTES", "say \"hi\" and 'bye'"),
+            ("BACKSLASHES", "C:\\path\\n \\\\ \\"),
+            ("MULTI", "line one\n\nline two\n"),
+            ("CRLF", "windows\r\nline\r"),
+            ("COMMENTS", "a # b ; c"),
+            ("SYNTAX", " env[X]=\"y\"\nZ=1 $HOME ${PATH} "),
+            ("UNICODE", "☃ ünïcödé\t\u{0}"),
+        ]);
+
+        for format in FORMATS.iter() {
+            let parsed = round_trip(format, &bag);
+
+            assert_eq!(Vec::<ParseError>::new(), parsed.errors, "{:?}", format);
+            assert_eq!(bag.params, parsed.params, "{:?}", format);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_round_trips_every_format(
+            params in prop::collection::btree_map(
+                "[A-Za-z_][A-Za-z0-9_.-]{0,16}",
+                prop_oneof![any::<String>(), "[ a\"'\\\\$#;=\\[\\]\r\n\t]{0,16}"],
+                0..8,
+            )
+        ) {
+            let params = params
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str()))
+                .collect::<Vec<_>>();
+            let bag = bag(&params);
+
+            for format in FORMATS.iter() {
+                let parsed = round_trip(format, &bag);
+
+                prop_assert_eq!(Vec::<ParseError>::new(), parsed.errors);
+                prop_assert_eq!(&bag.params, &parsed.params);
+            }
+        }
+    }
+
+    #[test]
+    fn test_emits_metadata_as_comments() {
+        let mut bag = bag(&[("HOST", "db")]);
+        bag.params[0].meta = Some(ParamMeta {
+            version: 2,
+            data_type: Some("text".to_string()),
+            ..meta("/app/host")
+        });
+
+        assert_eq!(
+            "# /app/host String version 2\nHOST=\"db\"",
+            Format::DotEnv
+                .format(&bag, true, ListStyle::Join)
+                .to_string()
+        );
+        assert_eq!(
+            "env[HOST]=\"db\"",
+            Format::PhpFpm
+                .format(&bag, false, ListStyle::Join)
+                .to_string()
+        );
+        assert_eq!(
+            "# /app/host String version 2\nHOST: db\n",
+            Format::Yaml.format(&bag, true, ListStyle::Join).to_string()
+        );
+
+        for format in FORMATS.iter() {
+            let formatted = format.format(&bag, true, ListStyle::Join).to_string();
+            let parsed = InputFormat::from(format).parse(&formatted);
+
+            assert_eq!(Vec::<ParseError>::new(), parsed.errors);
+            assert_eq!(
+                ("HOST", "db"),
+                (&*parsed.params[0].key, parsed.params[0].value.expose())
+            );
+        }
     }
 }
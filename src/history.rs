@@ -0,0 +1,516 @@
+use async_trait::async_trait;
+use aws_sdk_ssm::model::ParameterType;
+use aws_sdk_ssm::SdkError;
+use aws_smithy_types::date_time::Format;
+use aws_smithy_types::DateTime;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde_json::{json, Value};
+use tracing::debug;
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::params::{normalize_path, ParamBag};
+use crate::secret::Secret;
+use crate::writer::{Policies, Tier};
+
+/// Number of parameter histories fetched at once by `rewind`
+const CONCURRENCY: usize = 4;
+
+/// Number of times a throttled history request is tried before giving up
+const MAX_ATTEMPTS: u32 = 4;
+
+/// Wait before the first retry of a throttled request, doubled for each retry after it
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Error codes SSM uses when requests are being rate limited
+const THROTTLING_CODES: [&str; 3] = [
+    "ThrottlingException",
+    "Throttling",
+    "TooManyRequestsException",
+];
+
+/// A single version of a parameter
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamVersion {
+    pub version: i64,
+    pub value: Secret,
+    pub param_type: Option<ParameterType>,
+    pub description: Option<String>,
+    pub last_modified: Option<DateTime>,
+    /// The ARN of the user or role that stored this version
+    pub modified_by: Option<String>,
+    pub labels: Vec<String>,
+    pub tier: Option<Tier>,
+    pub policies: Policies,
+}
+
+#[async_trait]
+pub trait HistoryClient {
+    /// Fetches every version of a parameter, oldest first
+    async fn history(&self, name: &str, decrypt: bool)
+        -> Result<Vec<ParamVersion>, Box<dyn Error>>;
+}
+
+#[async_trait]
+impl HistoryClient for aws_sdk_ssm::Client {
+    async fn history(
+        &self,
+        name: &str,
+        decrypt: bool,
+    ) -> Result<Vec<ParamVersion>, Box<dyn Error>> {
+        let mut versions = vec![];
+        let mut next = None;
+
+        loop {
+            let resp = self
+                .get_parameter_history()
+                .name(name)
+                .with_decryption(decrypt)
+                .set_next_token(next)
+                .send()
+                .await
+                .map_err(|err| -> Box<dyn Error> {
+                    match err {
+                        SdkError::ServiceError { ref err, .. }
+                            if err
+                                .code()
+                                .is_some_and(|code| THROTTLING_CODES.contains(&code)) =>
+                        {
+                            Box::new(Throttled(err.to_string()))
+                        }
+                        err => Box::new(err),
+                    }
+                })?;
+
+            for entry in resp.parameters.unwrap_or_default() {
+                let policies = Policies::from_inline(name, &entry.policies.unwrap_or_default());
+
+                versions.push(ParamVersion {
+                    version: entry.version,
+                    value: entry.value.unwrap_or_default().into(),
+                    param_type: entry.r#type,
+                    description: entry.description,
+                    last_modified: entry.last_modified_date,
+                    modified_by: entry.last_modified_user,
+                    labels: entry.labels.unwrap_or_default(),
+                    tier: entry.tier.as_ref().and_then(Tier::from_parameter_tier),
+                    policies,
+                });
+            }
+
+            next = resp.next_token;
+
+            if next.is_none() {
+                break;
+            }
+        }
+
+        versions.sort_by_key(|v| v.version);
+
+        Ok(versions)
+    }
+}
+
+/// A point in time given as an RFC 3339 timestamp, or a date meaning midnight UTC
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timestamp(pub DateTime);
+
+impl FromStr for Timestamp {
+    type Err = TimestampError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let timestamp = if s.len() == 10 {
+            format!("{}T00:00:00Z", s)
+        } else {
+            s.to_string()
+        };
+
+        DateTime::from_str(&timestamp, Format::DateTime)
+            .map(Timestamp)
+            .map_err(|_| TimestampError(s.to_string()))
+    }
+}
+
+#[derive(Debug)]
+pub struct TimestampError(String);
+
+impl fmt::Display for TimestampError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} is not a timestamp like 2021-06-30T17:00:00Z or 2021-06-30",
+            self.0
+        )
+    }
+}
+
+impl Error for TimestampError {}
+
+impl Timestamp {
+    fn is_before(&self, time: &DateTime) -> bool {
+        (self.0.secs(), self.0.subsec_nanos()) < (time.secs(), time.subsec_nanos())
+    }
+}
+
+/// The version that was current at a point in time
+pub fn version_at<'a>(history: &'a [ParamVersion], at: &Timestamp) -> Option<&'a ParamVersion> {
+    history
+        .iter()
+        .filter(|v| matches!(&v.last_modified, Some(modified) if !at.is_before(modified)))
+        .max_by_key(|v| v.version)
+}
+
+/// A history request that SSM rejected because too many requests were made
+#[derive(Debug)]
+pub struct Throttled(String);
+
+impl fmt::Display for Throttled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for Throttled {}
+
+/// Fetches the history of a parameter named with or without its leading slash
+pub async fn history_of<T>(
+    client: &T,
+    name: &str,
+    decrypt: bool,
+) -> Result<Vec<ParamVersion>, Box<dyn Error>>
+where
+    T: HistoryClient,
+{
+    history_with_retry(client, &normalize_path(name), decrypt).await
+}
+
+/// Fetches the history of a parameter, retrying with a growing delay while it is throttled
+async fn history_with_retry<T>(
+    client: &T,
+    name: &str,
+    decrypt: bool,
+) -> Result<Vec<ParamVersion>, Box<dyn Error>>
+where
+    T: HistoryClient,
+{
+    let mut attempt = 1;
+
+    loop {
+        match client.history(name, decrypt).await {
+            Err(err) if err.is::<Throttled>() && attempt < MAX_ATTEMPTS => {
+                debug!(name, attempt, "History request was throttled, retrying");
+                tokio::time::sleep(RETRY_DELAY * 2u32.pow(attempt - 1)).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Replaces every value in a bag with the one it had at a point in time. Parameters created later
+/// are dropped. Parameters deleted since cannot be brought back, as SSM forgets their history.
+/// A few histories are fetched at once, and throttled requests are retried.
+pub async fn rewind<T>(client: &T, bag: &mut ParamBag, at: &Timestamp) -> Result<(), Box<dyn Error>>
+where
+    T: HistoryClient,
+{
+    bag.params.retain(|param| param.meta.is_some());
+
+    let decrypt = bag.decrypt;
+    let histories = stream::iter(bag.params.iter().filter_map(|param| param.meta.as_ref()))
+        .map(|meta| history_with_retry(client, &meta.name, decrypt))
+        .buffered(CONCURRENCY)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    let mut params = vec![];
+
+    for (mut param, history) in bag.params.drain(..).zip(histories) {
+        let meta = match param.meta.as_mut() {
+            Some(meta) => meta,
+            None => continue,
+        };
+
+        if let Some(version) = version_at(&history, at) {
+            meta.version = version.version;
+            meta.last_modified = version.last_modified;
+            meta.param_type = version.param_type.clone();
+            param.value = version.value.clone();
+            params.push(param);
+        }
+    }
+
+    bag.params = params;
+
+    Ok(())
+}
+
+/// The versions of a parameter, newest first
+pub struct History<'a> {
+    versions: &'a [ParamVersion],
+    show_values: bool,
+}
+
+impl<'a> From<&'a [ParamVersion]> for History<'a> {
+    fn from(versions: &'a [ParamVersion]) -> Self {
+        History {
+            versions,
+            show_values: false,
+        }
+    }
+}
+
+impl<'a> History<'a> {
+    pub fn with_values(mut self, show_values: bool) -> Self {
+        self.show_values = show_values;
+        self
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::Array(
+            self.versions
+                .iter()
+                .rev()
+                .map(|v| {
+                    let mut entry = json!({
+                        "version": v.version,
+                        "last_modified": v
+                            .last_modified
+                            .as_ref()
+                            .and_then(|t| t.fmt(Format::DateTime).ok()),
+                        "modified_by": v.modified_by,
+                        "labels": v.labels,
+                    });
+
+                    if self.show_values {
+                        entry["value"] = json!(v.value.expose());
+                    }
+
+                    entry
+                })
+                .collect(),
+        )
+    }
+}
+
+impl<'a> fmt::Display for History<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, v) in self.versions.iter().rev().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+
+            write!(f, "v{}", v.version)?;
+
+            if let Some(modified) = v
+                .last_modified
+                .as_ref()
+                .and_then(|t| t.fmt(Format::DateTime).ok())
+            {
+                write!(f, "  {}", modified)?;
+            }
+
+            if let Some(user) = &v.modified_by {
+                write!(f, "  {}", user)?;
+            }
+
+            if !v.labels.is_empty() {
+                write!(f, "  [{}]", v.labels.join(", "))?;
+            }
+
+            if self.show_values {
+                write!(f, "  = {}", v.value.expose().escape_debug())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::params::tests::ssm_bag;
+
+    fn version(version: i64, value: &str, secs: i64) -> ParamVersion {
+        ParamVersion {
+            version,
+            value: value.into(),
+            param_type: Some(ParameterType::String),
+            description: None,
+            last_modified: Some(DateTime::from_secs(secs)),
+            modified_by: Some("arn:aws:iam::123456789012:user/alice".to_string()),
+            labels: vec![],
+            tier: None,
+            policies: Policies::default(),
+        }
+    }
+
+    /// Answers from a map of histories, throttling the first requests for a name as many times
+    /// as it has throttles left
+    struct MapClient(
+        HashMap<String, Vec<ParamVersion>>,
+        Mutex<HashMap<String, u32>>,
+    );
+
+    impl MapClient {
+        fn new(histories: Vec<(&str, Vec<ParamVersion>)>) -> Self {
+            MapClient(
+                histories
+                    .into_iter()
+                    .map(|(name, history)| (name.to_string(), history))
+                    .collect(),
+                Mutex::default(),
+            )
+        }
+
+        fn throttle(self, name: &str, times: u32) -> Self {
+            self.1.lock().unwrap().insert(name.to_string(), times);
+            self
+        }
+    }
+
+    #[async_trait]
+    impl HistoryClient for MapClient {
+        async fn history(
+            &self,
+            name: &str,
+            _decrypt: bool,
+        ) -> Result<Vec<ParamVersion>, Box<dyn Error>> {
+            if let Some(times) = self.1.lock().unwrap().get_mut(name).filter(|t| **t > 0) {
+                *times -= 1;
+                return Err(Box::new(Throttled("Rate exceeded".to_string())));
+            }
+
+            Ok(self.0.get(name).cloned().unwrap_or_default())
+        }
+    }
+
+    #[test]
+    fn test_parses_timestamps() {
+        assert_eq!(
+            Timestamp(DateTime::from_secs(1_600_000_000)),
+            "2020-09-13T12:26:40Z".parse().unwrap()
+        );
+        assert_eq!(
+            Timestamp(DateTime::from_secs(1_599_955_200)),
+            "2020-09-13".parse().unwrap()
+        );
+        assert!("last tuesday".parse::<Timestamp>().is_err());
+    }
+
+    #[test]
+    fn test_finds_version_at_a_point_in_time() {
+        let history = vec![
+            version(1, "a", 100),
+            version(2, "b", 200),
+            version(3, "c", 300),
+        ];
+
+        assert_eq!(
+            None,
+            version_at(&history, &Timestamp(DateTime::from_secs(99)))
+        );
+        assert_eq!(
+            Some(2),
+            version_at(&history, &Timestamp(DateTime::from_secs(200))).map(|v| v.version)
+        );
+        assert_eq!(
+            Some(3),
+            version_at(&history, &Timestamp(DateTime::from_secs(1000))).map(|v| v.version)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_finds_history_with_or_without_leading_slash() {
+        let mut rotated = version(2, "new", 200);
+        rotated.tier = Some(Tier::Advanced);
+        rotated.policies.notify_unchanged_after = Some(90);
+        let client = MapClient::new(vec![("/app/db", vec![version(1, "old", 100), rotated])])
+            .throttle("/app/db", 1);
+
+        let history = history_of(&client, "app/db", false).await.unwrap();
+        assert_eq!(
+            history,
+            history_of(&client, "/app/db", false).await.unwrap()
+        );
+        assert_eq!(2, history.len());
+        assert_eq!(Some(Tier::Advanced), history[1].tier);
+        assert_eq!(Some(90), history[1].policies.notify_unchanged_after);
+    }
+
+    #[tokio::test]
+    async fn test_rewinds_a_bag() {
+        let client = MapClient::new(vec![
+            (
+                "/app/host",
+                vec![version(1, "old", 100), version(2, "new", 300)],
+            ),
+            ("/app/added", vec![version(1, "later", 400)]),
+        ]);
+
+        let mut bag = ssm_bag(&[("/app/host", "new"), ("/app/added", "later")]);
+
+        rewind(&client, &mut bag, &Timestamp(DateTime::from_secs(200)))
+            .await
+            .unwrap();
+
+        assert_eq!(1, bag.params.len());
+        assert_eq!("old", bag.params[0].value);
+        assert_eq!(1, bag.params[0].meta.as_ref().unwrap().version);
+    }
+
+    #[tokio::test]
+    async fn test_retries_throttled_requests_in_order() {
+        let names = (0..10).map(|i| format!("/app/{}", i)).collect::<Vec<_>>();
+        let client = MapClient::new(
+            names
+                .iter()
+                .map(|name| (name.as_str(), vec![version(1, name, 100)]))
+                .collect(),
+        )
+        .throttle("/app/3", 2);
+
+        let params = names
+            .iter()
+            .map(|name| (name.as_str(), "new"))
+            .collect::<Vec<_>>();
+        let mut bag = ssm_bag(&params);
+
+        rewind(&client, &mut bag, &Timestamp(DateTime::from_secs(200)))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            names,
+            bag.params
+                .iter()
+                .map(|param| param.value.expose())
+                .collect::<Vec<_>>()
+        );
+
+        let client = client.throttle("/app/3", MAX_ATTEMPTS);
+        let err = rewind(&client, &mut bag, &Timestamp(DateTime::from_secs(200)))
+            .await
+            .unwrap_err();
+        assert!(err.is::<Throttled>());
+    }
+
+    #[test]
+    fn test_prints_newest_version_first() {
+        let mut history = [version(1, "a", 100), version(2, "b\nc", 200)];
+        history[1].labels = vec!["stable".to_string()];
+
+        assert_eq!(
+            "v2  1970-01-01T00:03:20Z  arn:aws:iam::123456789012:user/alice  [stable]  = b\\nc\nv1  1970-01-01T00:01:40Z  arn:aws:iam::123456789012:user/alice  = a",
+            History::from(&history[..]).with_values(true).to_string()
+        );
+        assert_eq!(2, History::from(&history[..]).to_json()[0]["version"]);
+    }
+}
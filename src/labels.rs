@@ -0,0 +1,237 @@
+use async_trait::async_trait;
+use aws_sdk_ssm::model::Parameter;
+use tracing::debug;
+
+use std::error::Error;
+
+use crate::output;
+use crate::params::ParamBag;
+
+/// Maximum number of names accepted by a single `GetParameters` request
+const BATCH_SIZE: usize = 10;
+
+#[async_trait]
+pub trait LabelClient {
+    /// Fetches the versions of the given names that carry a label. Names without the label are
+    /// left out.
+    async fn labeled(
+        &self,
+        names: &[String],
+        label: &str,
+        decrypt: bool,
+    ) -> Result<Vec<Parameter>, Box<dyn Error>>;
+
+    /// Moves a label to the newest version of a parameter, returning that version
+    async fn label(&self, name: &str, label: &str) -> Result<i64, Box<dyn Error>>;
+}
+
+#[async_trait]
+impl LabelClient for aws_sdk_ssm::Client {
+    async fn labeled(
+        &self,
+        names: &[String],
+        label: &str,
+        decrypt: bool,
+    ) -> Result<Vec<Parameter>, Box<dyn Error>> {
+        let resp = self
+            .get_parameters()
+            .set_names(Some(
+                names
+                    .iter()
+                    .map(|name| format!("{}:{}", name, label))
+                    .collect(),
+            ))
+            .with_decryption(decrypt)
+            .send()
+            .await
+            .map_err(Box::new)?;
+
+        Ok(resp.parameters.unwrap_or_default())
+    }
+
+    async fn label(&self, name: &str, label: &str) -> Result<i64, Box<dyn Error>> {
+        let resp = self
+            .label_parameter_version()
+            .name(name)
+            .labels(label)
+            .send()
+            .await
+            .map_err(Box::new)?;
+
+        match resp.invalid_labels {
+            Some(invalid) if !invalid.is_empty() => {
+                Err(format!("{} is not a valid label", invalid.join(", ")).into())
+            }
+            _ => Ok(resp.parameter_version),
+        }
+    }
+}
+
+/// Replaces every value in a bag with its labeled version. Parameters without the label are
+/// dropped with a warning.
+pub async fn pin<T>(client: &T, bag: &mut ParamBag, label: &str) -> Result<(), Box<dyn Error>>
+where
+    T: LabelClient,
+{
+    let names = bag
+        .params
+        .iter()
+        .filter_map(|param| param.meta.as_ref().map(|meta| meta.name.clone()))
+        .collect::<Vec<_>>();
+    let mut labeled = vec![];
+
+    for chunk in names.chunks(BATCH_SIZE) {
+        debug!(?chunk, label, "Fetching labeled versions");
+        labeled.extend(client.labeled(chunk, label, bag.decrypt).await?);
+    }
+
+    let find = |name: &str| {
+        labeled
+            .iter()
+            .find(|parameter| parameter.name.as_deref() == Some(name))
+    };
+
+    bag.params.retain(|param| match &param.meta {
+        Some(meta) if find(&meta.name).is_some() => true,
+        Some(meta) => {
+            output::warning(format!("{} has no version labeled {}", meta.name, label));
+            false
+        }
+        None => false,
+    });
+
+    for param in bag.params.iter_mut() {
+        if let Some(meta) = param.meta.as_mut() {
+            if let Some(parameter) = find(&meta.name) {
+                param.value = parameter.value.clone().unwrap_or_default().into();
+                meta.version = parameter.version;
+                meta.last_modified = parameter.last_modified_date;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The outcome of labeling parameters
+#[derive(Debug, Default, PartialEq)]
+pub struct LabelSummary {
+    pub labeled: Vec<String>,
+    pub failed: Vec<(String, String)>,
+}
+
+impl LabelSummary {
+    /// Fails when any parameter could not be labeled, so that `label` exits with an error
+    pub fn check(&self) -> Result<(), String> {
+        if self.failed.is_empty() {
+            return Ok(());
+        }
+
+        Err(format!(
+            "Failed to label {} of {} parameters",
+            self.failed.len(),
+            self.failed.len() + self.labeled.len()
+        ))
+    }
+}
+
+/// Labels the newest version of every parameter in a bag
+pub async fn label<T>(client: &T, bag: &ParamBag, label: &str) -> LabelSummary
+where
+    T: LabelClient,
+{
+    let mut summary = LabelSummary::default();
+
+    for meta in bag.params.iter().filter_map(|param| param.meta.as_ref()) {
+        match client.label(&meta.name, label).await {
+            Ok(version) => {
+                output::status(format!("Labeled {} version {}", meta.name, version));
+                summary.labeled.push(meta.name.clone());
+            }
+            Err(err) => {
+                output::error(format!("Failed to label {} due to {}", meta.name, err));
+                summary.failed.push((meta.name.clone(), err.to_string()));
+            }
+        }
+    }
+
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::params::tests::ssm_bag;
+
+    #[derive(Default)]
+    struct LabeledClient {
+        labeled: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl LabelClient for LabeledClient {
+        async fn labeled(
+            &self,
+            names: &[String],
+            label: &str,
+            _decrypt: bool,
+        ) -> Result<Vec<Parameter>, Box<dyn Error>> {
+            Ok(names
+                .iter()
+                .filter(|name| name.as_str() != "/app/new")
+                .map(|name| {
+                    Parameter::builder()
+                        .name(name)
+                        .value(format!("{} at {}", name, label))
+                        .version(2)
+                        .build()
+                })
+                .collect())
+        }
+
+        async fn label(&self, name: &str, label: &str) -> Result<i64, Box<dyn Error>> {
+            if label.starts_with("aws") {
+                return Err(format!("{} is not a valid label", label).into());
+            }
+
+            self.labeled.lock().unwrap().push(name.to_string());
+            Ok(5)
+        }
+    }
+
+    fn bag() -> ParamBag {
+        ssm_bag(&[("/app/host", "latest"), ("/app/new", "latest")])
+    }
+
+    #[tokio::test]
+    async fn test_pins_values_to_a_label() {
+        let mut bag = bag();
+
+        pin(&LabeledClient::default(), &mut bag, "release")
+            .await
+            .unwrap();
+
+        assert_eq!(1, bag.params.len());
+        assert_eq!("/app/host at release", bag.params[0].value);
+        assert_eq!(2, bag.params[0].meta.as_ref().unwrap().version);
+    }
+
+    #[tokio::test]
+    async fn test_labels_every_parameter() {
+        let client = LabeledClient::default();
+
+        let summary = label(&client, &bag(), "release").await;
+        assert_eq!(vec!["/app/host", "/app/new"], summary.labeled);
+        assert_eq!(summary.labeled, *client.labeled.lock().unwrap());
+        assert_eq!(Ok(()), summary.check());
+
+        let summary = label(&client, &bag(), "aws-release").await;
+        assert_eq!(2, summary.failed.len());
+        assert_eq!(
+            Err("Failed to label 2 of 2 parameters".to_string()),
+            summary.check()
+        );
+    }
+}
@@ -1,125 +1,595 @@
 //! A small command line utility for reading parameters from a path in
 //! the AWS Systems Manager Parameter Store and outputting them in a given
-//! format.
+//! format, and for writing and managing them.
 //!
-//! Parameters are expected to have keys stored in Parameter Store under an
-//! AWS path format.
-//!
-//! `/path1/path2/path3/param`
-//!
-//! Two output formats are currently support: `.env` and `php-fpm.conf`
-//!
-//! `envfmt /path/to/ dot-env > .env`
-//!
-//! `envfmt /path/to/ php-fpm > env.conf`
-//!
-//! The region to use can be specified with the `region` flag.
-//!
-//! `envfmt /path/to/ dot-env --region us-west-1 > .env`
-//!
-//! If left unspecified the region will attempt to be read from the current
-//! environment. In the case that it fails, it will fall back to us-east-1.
+//! See `README.md` for the commands, options and examples.
 
+use aws_config::default_provider::credentials::DefaultCredentialsChain;
 use aws_config::default_provider::region::DefaultRegionChain;
 use aws_config::meta::credentials::LazyCachingCredentialsProvider;
+use aws_config::provider_config::ProviderConfig;
+use aws_config::sts::AssumeRoleProvider;
+use aws_config::web_identity_token::{StaticConfiguration, WebIdentityTokenCredentialsProvider};
+use aws_sdk_ssm::model::ParameterType;
+use aws_sdk_ssm::{Endpoint, Region};
 use aws_types::credentials::SharedCredentialsProvider;
 use clap::Parser;
 
+use std::collections::HashMap;
 use std::error::Error;
-use std::fmt::Display;
 use std::io::Write;
 use std::time::Duration;
 
+mod cache;
+mod expand;
+mod filter;
 mod formatter;
+mod history;
+mod labels;
 mod mfa;
 mod opt;
+mod output;
 mod params;
+mod parser;
+mod references;
+mod remove;
+mod secret;
+mod sso;
+mod tags;
+mod token;
+mod transform;
+mod tree;
 mod writer;
 
-use crate::formatter::{DotEnv, PhpFpm};
-use crate::opt::{Command, EnvFmtOpts, Format};
-use crate::params::{get_all_params_for_path, ParamBag};
-use crate::writer::Writer;
+use crate::cache::CredentialCache;
+use crate::filter::KeyFilter;
+use crate::history::History;
+use crate::mfa::RoleStep;
+use crate::opt::{Command, EnvFmtOpts, Format, OutputFormat};
+use crate::output::{ReportCredentialErrors, Verbosity};
+use crate::params::{get_all_params, normalize_path, ParamBag};
+use crate::references::ReferenceResolver;
+use crate::secret::MASK;
+use crate::sso::SsoCredentialsProvider;
+use crate::token::TokenSource;
+use crate::transform::Case;
+use crate::tree::ParamTree;
+use crate::writer::{copied_name, full_name, ValueSource, Writer};
+
+/// Builds a credentials provider for the authentication options, or `None` when the default
+/// provider chain should be used
+async fn credentials_provider(
+    opts: &EnvFmtOpts,
+    region: Option<Region>,
+) -> Result<Option<SharedCredentialsProvider>, Box<dyn Error>> {
+    let provider = if opts.uses_mfa() {
+        let mut mfa_provider = mfa::AssumeRoleWithMFATokenProvider::new();
+        mfa_provider.set_profile(opts.profile.clone());
+        mfa_provider.set_region(region);
+        mfa_provider.set_role(
+            opts.role_arn
+                .as_ref()
+                .map(|role| RoleStep::new(role, opts.external_id.clone())),
+        );
+        mfa_provider.set_token_source(TokenSource::select(
+            opts.mfa_token.clone(),
+            opts.mfa_command.clone(),
+            opts.mfa_totp_file.clone(),
+            opts.mfa_totp_keyring.clone(),
+        ));
+        mfa_provider.set_cache(if opts.no_cache {
+            None
+        } else {
+            CredentialCache::new()
+        });
+
+        SharedCredentialsProvider::new(mfa_provider)
+    } else if let Some(ref token_file) = opts.web_identity_token_file {
+        SharedCredentialsProvider::new(
+            WebIdentityTokenCredentialsProvider::builder()
+                .configure(&ProviderConfig::without_region().with_region(region))
+                .static_configuration(StaticConfiguration {
+                    web_identity_token_file: token_file.into(),
+                    role_arn: opts.role_arn.clone().unwrap_or_default(),
+                    session_name: "envfmt".to_string(),
+                })
+                .build(),
+        )
+    } else {
+        let sso = match opts.profile {
+            Some(ref profile) => SsoCredentialsProvider::for_profile(profile).await?,
+            None => None,
+        };
+
+        let base = match sso {
+            Some(sso) => SharedCredentialsProvider::new(sso),
+            None if opts.profile.is_none() && opts.role_arn.is_none() => return Ok(None),
+            None => {
+                let mut chain = DefaultCredentialsChain::builder().region(region.clone());
+                if let Some(ref profile) = opts.profile {
+                    chain = chain.profile_name(profile);
+                }
+                SharedCredentialsProvider::new(chain.build().await)
+            }
+        };
+
+        match opts.role_arn {
+            Some(ref role) => {
+                let mut assume_role = AssumeRoleProvider::builder(role).session_name("envfmt");
+                if let Some(ref external_id) = opts.external_id {
+                    assume_role = assume_role.external_id(external_id);
+                }
+                if let Some(region) = region {
+                    assume_role = assume_role.region(region);
+                }
+                SharedCredentialsProvider::new(assume_role.build(base))
+            }
+            None => base,
+        }
+    };
+
+    let lazy_provider = LazyCachingCredentialsProvider::builder()
+        .load(ReportCredentialErrors(provider))
+        .load_timeout(Duration::from_secs(60))
+        .build();
+
+    Ok(Some(SharedCredentialsProvider::new(lazy_provider)))
+}
 
 #[tokio::main]
-pub async fn main() -> Result<(), Box<dyn Error>> {
+pub async fn main() {
     let opts = EnvFmtOpts::parse();
 
+    output::set_verbosity(Verbosity::from_flags(opts.quiet, opts.verbose));
+
     if opts.debug {
-        tracing_subscriber::fmt::init();
+        tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_writer(std::io::stderr)
+            .init();
     }
 
-    let conf = if opts.mfa || opts.mfa_token.is_some() {
-        let region = DefaultRegionChain::builder()
-            .profile_name(
-                opts.profile
-                    .as_ref()
-                    .map(|s| s.as_str())
-                    .unwrap_or("default"),
-            )
-            .build()
-            .region()
-            .await;
+    if let Err(err) = run(opts).await {
+        tracing::error!(%err, "Failed to run command");
+        output::error(err);
+        std::process::exit(1);
+    }
+}
 
-        let mut mfa_provider = mfa::AssumeRoleWithMFATokenProvider::new();
-        mfa_provider.set_profile(opts.profile);
-        mfa_provider.set_token(opts.mfa_token);
+async fn run(opts: EnvFmtOpts) -> Result<(), Box<dyn Error>> {
+    let region = match opts.region {
+        Some(ref region) => Some(Region::new(region.clone())),
+        None => {
+            let mut chain = DefaultRegionChain::builder();
+            if let Some(ref profile) = opts.profile {
+                chain = chain.profile_name(profile);
+            }
+            chain.build().region().await
+        }
+    };
 
-        let lazy_provider = LazyCachingCredentialsProvider::builder()
-            .load(mfa_provider)
-            .load_timeout(Duration::from_secs(60))
-            .build();
+    let mut loader = aws_config::from_env().region(region.clone());
+    if let Some(credentials) = credentials_provider(&opts, region).await? {
+        loader = loader.credentials_provider(credentials);
+    }
 
-        let conf = aws_config::Config::builder()
-            .region(region)
-            .credentials_provider(SharedCredentialsProvider::new(lazy_provider))
-            .build();
+    let conf = loader.load().await;
 
-        conf
-    } else {
-        aws_config::load_from_env().await
-    };
+    let mut ssm_conf = aws_sdk_ssm::config::Builder::from(&conf);
+    if let Some(endpoint_url) = opts.endpoint_url {
+        ssm_conf = ssm_conf.endpoint_resolver(Endpoint::immutable(endpoint_url));
+    }
 
-    let client = aws_sdk_ssm::Client::new(&conf);
+    let client = aws_sdk_ssm::Client::from_conf(ssm_conf.build());
 
-    let result = match opts.command {
-        Command::Read { ref path } => {
-            let res = get_all_params_for_path(&client, &path).await;
+    match opts.command {
+        Command::Read {
+            ref path,
+            ref include,
+            ref exclude,
+            regex,
+            ref filter,
+            ref transform,
+            invalid_names,
+            expand,
+            resolve_refs,
+            metadata,
+            at,
+            ref label,
+            ref tag,
+            string_lists,
+            ref out,
+        } => {
+            if metadata && matches!(opts.format, Some(Format::Json)) {
+                return Err(
+                    "--metadata cannot be used with --format json, as JSON has no comments".into(),
+                );
+            }
 
-            if let Ok(ref bag) = res {
-                let formatted: Box<dyn Display> = match opts.format.unwrap_or(Format::DotEnv) {
-                    Format::DotEnv => Box::new(DotEnv::from(bag)),
-                    Format::PhpFpm => Box::new(PhpFpm::from(bag)),
-                };
+            let key_filter = KeyFilter::new(include, exclude, regex)?;
+            let bag = ParamBag::new(path)
+                .with_filters(filter.clone())
+                .with_transform(transform.to_transform(Case::Upper)?);
 
-                if let Some(out_file) = opts.out {
-                    let mut file = std::fs::File::create(out_file)?;
-                    file.write_all(format!("{}", formatted).as_bytes())?;
-                } else {
-                    print!("{}", formatted);
-                }
+            let mut bag = get_all_params(&client, bag)
+                .await
+                .map_err(|err| format!("Failed to get parameters: {}", err))?;
+
+            if let Some(at) = at {
+                history::rewind(&client, &mut bag, &at)
+                    .await
+                    .map_err(|err| format!("Failed to get parameter history: {}", err))?;
+            }
+
+            if let Some(label) = label {
+                labels::pin(&client, &mut bag, label)
+                    .await
+                    .map_err(|err| format!("Failed to get labeled parameters: {}", err))?;
+            }
+
+            if !tag.is_empty() {
+                tags::keep_tagged(&client, &mut bag, tag)
+                    .await
+                    .map_err(|err| format!("Failed to get tagged parameters: {}", err))?;
+            }
+
+            output::verbose(format!(
+                "Read {} parameters from {}",
+                bag.params.len(),
+                path
+            ));
+
+            if resolve_refs {
+                ReferenceResolver::new(&client).resolve(&mut bag).await?;
+            }
+
+            // Values may reference keys which are filtered out, so expansion gets a copy of
+            // the values from before filtering
+            let outside = if expand {
+                expand::values(&bag)
+            } else {
+                HashMap::new()
+            };
+
+            key_filter.apply(&mut bag);
+            invalid_names.apply(&mut bag)?;
+
+            if expand {
+                expand::expand(&mut bag, &outside)?;
+            }
+
+            string_lists.apply(&mut bag)?;
+
+            if opts.mask {
+                bag.mask();
             }
 
-            res.map(|_| ())
+            let formatted =
+                opts.format
+                    .unwrap_or(Format::DotEnv)
+                    .format(&bag, metadata, string_lists);
+
+            if let Some(out_file) = out.as_ref().or(opts.out.as_ref()) {
+                let mut file = std::fs::File::create(out_file)?;
+                file.write_all(format!("{}", formatted).as_bytes())?;
+            } else {
+                print!("{}", formatted);
+            }
+
+            Ok(())
         }
         Command::Write {
             ref prefix,
             ref file_path,
+            input_format,
             ref overwrite,
+            ref transform,
+            output,
+            parse_mode,
+            ref tag,
+            ref description,
+            ref storage,
+            ref list,
         } => {
-            let writer = Writer::new(client, *overwrite);
-            let bag = ParamBag::from_dotenv(file_path, &prefix.as_ref().unwrap_or(&"".to_string()));
+            let writer = Writer::new(client, *overwrite)
+                .with_tags(tag.clone())
+                .with_lists(list.clone())
+                .with_description(description.clone())
+                .with_tier(storage.tier)
+                .with_policies(storage.to_policies())
+                .with_transform(transform.to_transform(Case::Lower)?.inverted());
+            let bag = ParamBag::from_input(
+                file_path,
+                prefix.as_deref().unwrap_or(""),
+                input_format,
+                parse_mode,
+            )?;
+
+            let summary = writer.write(&bag).await;
 
-            writer.write(&bag).await;
+            output::status(format!(
+                "Wrote {} parameters, {} failed",
+                summary.written.len(),
+                summary.failed.len()
+            ));
+
+            if output == OutputFormat::Json {
+                println!("{}", summary.to_json());
+            }
+
+            Ok(summary.check()?)
+        }
+        Command::Ls {
+            ref path,
+            recursive,
+            show_values,
+            json,
+        } => {
+            let mut bag = tree::list(&client, path, recursive, show_values)
+                .await
+                .map_err(|err| format!("Failed to list parameters: {}", err))?;
+
+            if opts.mask {
+                bag.mask();
+            }
+
+            let tree = ParamTree::from(&bag).with_values(show_values);
+
+            if json {
+                println!("{}", tree.to_json());
+            } else {
+                println!("{}", tree);
+            }
 
             Ok(())
         }
-    };
+        Command::Get {
+            ref name,
+            ref transform,
+        } => {
+            let name = full_name(&transform.to_transform(Case::Lower)?.inverted(), name);
+            let resp = client
+                .get_parameter()
+                .name(&name)
+                .with_decryption(true)
+                .send()
+                .await
+                .map_err(|err| format!("Failed to get {}: {}", name, err))?;
 
-    if result.is_err() {
-        tracing::error!(?result, "Failed to get parameters from remote");
-        println!("Failed to get paramaters");
-    }
+            if let Some(value) = resp.parameter.and_then(|p| p.value) {
+                println!("{}", if opts.mask { MASK } else { &value });
+            }
+
+            Ok(())
+        }
+        Command::Set {
+            ref name,
+            ref value,
+            ref from_file,
+            secure,
+            ref description,
+            overwrite,
+            ref transform,
+            ref tag,
+            ref storage,
+            list,
+        } => {
+            let transform = transform.to_transform(Case::Lower)?.inverted();
+            let name = full_name(&transform, name);
+            let writer = Writer::new(client, overwrite)
+                .with_secure(secure)
+                .with_tags(tag.clone())
+                .with_description(description.clone())
+                .with_tier(storage.tier)
+                .with_policies(storage.to_policies())
+                .with_transform(transform);
+
+            let value =
+                ValueSource::from_args(value.as_deref(), from_file.as_deref()).read(&name)?;
+            let param_type = if list {
+                ParameterType::StringList
+            } else {
+                writer.param_type(&name)
+            };
+            let version = writer
+                .put(&name, &value, param_type)
+                .await
+                .map_err(|err| format!("Failed to write {}: {}", name, err))?;
+
+            output::status(format!("Wrote {} version {}", name, version));
+
+            Ok(())
+        }
+        Command::Rm {
+            ref name,
+            recursive,
+            ref backup,
+            yes,
+        } => {
+            let name = normalize_path(name);
+            let mut params = remove::targets(&client, &name, recursive, backup.is_some()).await?;
+
+            if params.is_empty() {
+                output::status(format!("Nothing to delete below {}", name));
+                return Ok(());
+            }
+
+            if let Some(backup) = backup {
+                tags::fetch_tags(&client, &mut params)
+                    .await
+                    .map_err(|err| format!("Failed to get tags: {}", err))?;
+                remove::save_backup(backup, &params)
+                    .map_err(|err| format!("Failed to save backup to {}: {}", backup, err))?;
+                output::status(format!("Saved {} parameters to {}", params.len(), backup));
+            }
+
+            let names = params
+                .iter()
+                .filter_map(|param| param.meta.as_ref().map(|meta| meta.name.clone()))
+                .collect::<Vec<_>>();
+
+            if recursive {
+                output::status(format!("Deleting {} parameters:", names.len()));
+                for name in &names {
+                    output::status(format!("  {}", name));
+                }
+
+                let question = format!("Delete {} parameters below {}?", names.len(), name);
+                if !yes && !output::confirm(question) {
+                    return Err("Nothing was deleted, pass --yes to delete without asking".into());
+                }
+            }
+
+            let summary = remove::remove(&client, &names).await;
+
+            output::status(format!(
+                "Deleted {} parameters, {} failed",
+                summary.deleted.len(),
+                summary.failed.len()
+            ));
+
+            Ok(summary.check()?)
+        }
+        Command::Export {
+            ref path,
+            ref file_path,
+        } => {
+            let path = normalize_path(path);
+            let mut params = remove::targets(&client, &path, true, true).await?;
+
+            tags::fetch_tags(&client, &mut params)
+                .await
+                .map_err(|err| format!("Failed to get tags: {}", err))?;
+            remove::save_backup(file_path, &params)
+                .map_err(|err| format!("Failed to export to {}: {}", file_path, err))?;
+
+            output::status(format!(
+                "Saved {} parameters to {}",
+                params.len(),
+                file_path
+            ));
+
+            Ok(())
+        }
+        Command::Copy {
+            ref from,
+            ref to,
+            overwrite,
+        } => {
+            let mut params = remove::targets(&client, &normalize_path(from), true, true).await?;
 
-    Ok(())
+            tags::fetch_tags(&client, &mut params)
+                .await
+                .map_err(|err| format!("Failed to get tags: {}", err))?;
+
+            for meta in params.iter_mut().filter_map(|param| param.meta.as_mut()) {
+                meta.name = copied_name(from, to, &meta.name);
+            }
+
+            let summary = Writer::new(client, overwrite).restore(&params).await;
+
+            output::status(format!(
+                "Copied {} parameters, {} failed",
+                summary.written.len(),
+                summary.failed.len()
+            ));
+
+            Ok(summary.check()?)
+        }
+        Command::Restore {
+            ref file_path,
+            overwrite,
+        } => {
+            let contents = std::fs::read_to_string(file_path)
+                .map_err(|err| format!("Failed to read {}: {}", file_path, err))?;
+            let params = remove::read_backup(&contents)
+                .map_err(|err| format!("Failed to restore {}: {}", file_path, err))?;
+
+            let summary = Writer::new(client, overwrite).restore(&params).await;
+
+            output::status(format!(
+                "Restored {} parameters, {} failed",
+                summary.written.len(),
+                summary.failed.len()
+            ));
+
+            Ok(summary.check()?)
+        }
+        Command::History {
+            ref name,
+            show_values,
+            output,
+        } => {
+            let mut versions = history::history_of(&client, name, show_values)
+                .await
+                .map_err(|err| format!("Failed to get the history of {}: {}", name, err))?;
+
+            if opts.mask {
+                for version in versions.iter_mut() {
+                    version.value = MASK.into();
+                }
+            }
+
+            let history = History::from(&versions[..]).with_values(show_values);
+
+            match output {
+                OutputFormat::Text => println!("{}", history),
+                OutputFormat::Json => println!("{}", history.to_json()),
+            }
+
+            Ok(())
+        }
+        Command::Rollback {
+            ref name,
+            to_version,
+        } => {
+            let name = &normalize_path(name);
+            let versions = history::history_of(&client, name, true)
+                .await
+                .map_err(|err| format!("Failed to get the history of {}: {}", name, err))?;
+            let target = versions
+                .iter()
+                .find(|v| v.version == to_version)
+                .ok_or_else(|| format!("{} has no version {}", name, to_version))?;
+
+            let writer = Writer::new(client, true)
+                .with_description(target.description.clone())
+                .with_tier(target.tier)
+                .with_policies(target.policies.clone());
+            let version = writer
+                .put(
+                    name,
+                    target.value.expose(),
+                    target.param_type.clone().unwrap_or(ParameterType::String),
+                )
+                .await
+                .map_err(|err| format!("Failed to write {}: {}", name, err))?;
+
+            output::status(format!(
+                "Rolled {} back to version {} as version {}",
+                name, to_version, version
+            ));
+
+            Ok(())
+        }
+        Command::Label {
+            ref path,
+            ref label,
+        } => {
+            let bag = get_all_params(&client, ParamBag::new(path))
+                .await
+                .map_err(|err| format!("Failed to get parameters: {}", err))?;
+
+            let summary = labels::label(&client, &bag, label).await;
+
+            output::status(format!(
+                "Labeled {} parameters as {}, {} failed",
+                summary.labeled.len(),
+                label,
+                summary.failed.len()
+            ));
+
+            Ok(summary.check()?)
+        }
+    }
 }
@@ -1,112 +1,377 @@
-use std::{convert::TryFrom, io::Write, time::SystemTime};
+use std::{
+    convert::TryFrom,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use aws_config::{
     default_provider::region::DefaultRegionChain,
     profile::{Profile, ProfileSet},
 };
-use aws_types::credentials::{CredentialsError, ProvideCredentials, SharedCredentialsProvider};
-use tracing::instrument;
+use aws_types::credentials::{
+    Credentials, CredentialsError, ProvideCredentials, SharedCredentialsProvider,
+};
+use aws_types::region::Region;
+use tracing::{instrument, warn};
+
+use crate::cache::CredentialCache;
+use crate::sso::SsoCredentialsProvider;
+use crate::token::TokenSource;
 
 #[derive(Debug)]
 pub struct AssumeRoleWithMFATokenProvider {
     profile: Option<String>,
-    token: Option<String>,
+    region: Option<Region>,
+    role: Option<RoleStep>,
+    token_source: TokenSource,
+    cache: Option<CredentialCache>,
 }
 
 impl AssumeRoleWithMFATokenProvider {
     pub fn new() -> Self {
         Self {
             profile: None,
-            token: None,
+            region: None,
+            role: None,
+            token_source: TokenSource::Prompt,
+            cache: None,
         }
     }
 
+    pub fn set_cache(&mut self, cache: Option<CredentialCache>) -> &mut Self {
+        self.cache = cache;
+        self
+    }
+
     pub fn set_profile(&mut self, profile: Option<impl Into<String>>) -> &mut Self {
         self.profile = profile.map(|p| p.into());
         self
     }
 
-    pub fn set_token(&mut self, token: Option<impl Into<String>>) -> &mut Self {
-        self.token = token.map(|t| t.into());
+    /// Region for STS requests. Defaults to the region of the profile.
+    pub fn set_region(&mut self, region: Option<Region>) -> &mut Self {
+        self.region = region;
+        self
+    }
+
+    /// A role to assume after the roles of the profile
+    pub fn set_role(&mut self, role: Option<RoleStep>) -> &mut Self {
+        self.role = role;
+        self
+    }
+
+    pub fn set_token_source(&mut self, token_source: TokenSource) -> &mut Self {
+        self.token_source = token_source;
         self
     }
 }
 
-struct AssumeRoleWithMFATokenProviderRequest {
+/// Where the credentials used to assume the first role in a chain come from
+#[derive(Debug, PartialEq)]
+enum BaseCredentials {
+    Static {
+        key: String,
+        secret: String,
+        session_token: Option<String>,
+    },
+    /// A `credential_source` of `Environment`, `Ec2InstanceMetadata` or `EcsContainer`
+    Source(String),
+    /// A profile assuming a role with `web_identity_token_file`, which is loaded by the
+    /// standard profile provider
+    WebIdentity(String),
+    /// A profile configured for AWS SSO
+    Sso(SsoCredentialsProvider),
+}
+
+impl BaseCredentials {
+    fn provider(&self) -> Result<SharedCredentialsProvider, String> {
+        match self {
+            BaseCredentials::Static {
+                key,
+                secret,
+                session_token,
+            } => Ok(SharedCredentialsProvider::new(Credentials::new(
+                key,
+                secret,
+                session_token.clone(),
+                None,
+                "assumed-role-credentials",
+            ))),
+            BaseCredentials::Source(source) => match source.as_str() {
+                "Environment" => Ok(SharedCredentialsProvider::new(
+                    aws_config::environment::credentials::EnvironmentVariableCredentialsProvider::new(),
+                )),
+                "Ec2InstanceMetadata" => Ok(SharedCredentialsProvider::new(
+                    aws_config::imds::credentials::ImdsCredentialsProvider::builder().build(),
+                )),
+                "EcsContainer" => Ok(SharedCredentialsProvider::new(
+                    aws_config::ecs::EcsCredentialsProvider::builder().build(),
+                )),
+                other => Err(format!("Unsupported credential_source {}", other)),
+            },
+            BaseCredentials::WebIdentity(profile) => Ok(SharedCredentialsProvider::new(
+                aws_config::profile::ProfileFileCredentialsProvider::builder()
+                    .profile_name(profile)
+                    .build(),
+            )),
+            BaseCredentials::Sso(provider) => Ok(SharedCredentialsProvider::new(provider.clone())),
+        }
+    }
+}
+
+/// A single role to assume, along with the settings from the profile that declared it
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoleStep {
     role: String,
-    key: String,
-    secret: String,
-    mfa_serial: String,
+    external_id: Option<String>,
+    session_name: Option<String>,
+    duration_seconds: Option<i32>,
+    mfa_serial: Option<String>,
 }
 
-impl AssumeRoleWithMFATokenProviderRequest {
-    pub fn new(
-        role: impl Into<String>,
-        key: impl Into<String>,
-        secret: impl Into<String>,
-        mfa_serial: impl Into<String>,
-    ) -> Self {
-        Self {
+impl RoleStep {
+    pub fn new(role: impl Into<String>, external_id: Option<String>) -> Self {
+        RoleStep {
             role: role.into(),
-            key: key.into(),
-            secret: secret.into(),
-            mfa_serial: mfa_serial.into(),
+            external_id,
+            session_name: None,
+            duration_seconds: None,
+            mfa_serial: None,
+        }
+    }
+
+    fn from_profile(profile: &Profile) -> Result<Option<Self>, String> {
+        // Web identity profiles are assumed by the base provider rather than chained
+        if profile.get("web_identity_token_file").is_some() {
+            return Ok(None);
         }
+
+        let role = match profile.get("role_arn") {
+            Some(role) => role,
+            None => return Ok(None),
+        };
+
+        let duration_seconds = profile
+            .get("duration_seconds")
+            .map(|d| {
+                d.parse::<i32>().map_err(|_| {
+                    format!(
+                        "Invalid duration_seconds {} in profile {}",
+                        d,
+                        profile.name()
+                    )
+                })
+            })
+            .transpose()?;
+
+        Ok(Some(RoleStep {
+            role: role.to_string(),
+            external_id: profile.get("external_id").map(|s| s.to_string()),
+            session_name: profile.get("role_session_name").map(|s| s.to_string()),
+            duration_seconds,
+            mfa_serial: profile.get("mfa_serial").map(|s| s.to_string()),
+        }))
     }
+}
 
+/// The flattened chain of roles to assume for a profile, following the same rules as the AWS
+/// CLI: each profile with a `role_arn` adds a role to the chain and its `source_profile` or
+/// `credential_source` says where the credentials to assume it come from. Source profiles with
+/// static keys end the chain. An explicit role is assumed last, using the `mfa_serial` of the
+/// profile if the profile does not assume a role itself.
+#[derive(Debug, PartialEq)]
+struct AssumeRoleWithMFATokenProviderRequest {
+    base: BaseCredentials,
+    chain: Vec<RoleStep>,
+}
+
+impl AssumeRoleWithMFATokenProviderRequest {
     pub async fn from_profile_set(
         profile_name: &str,
         set: &ProfileSet,
-    ) -> Result<Self, &'static str> {
-        let profile = set.get_profile(profile_name);
-        let source = Self::get_source_profile(profile_name, set);
-
-        if let (Some(profile), Some(source)) = (profile, source) {
-            let profiles = [profile, source];
-
-            let role = profile
-                .get("role_arn")
-                .ok_or("Failed to find a role to assume in selected profile")?;
-            let key = Self::extract_field("aws_access_key_id", &profiles)
-                .ok_or("Failed to find an access key in source profile for selected profile")?;
-            let secret = Self::extract_field("aws_secret_access_key", &profiles)
-                .ok_or("Failed to find a secret key in source profile for selected profile")?;
-            let mfa_serial = profile
-                .get("mfa_serial")
-                .ok_or("Failed to find a mfa serial to use in selected profile")?;
-
-            Ok(AssumeRoleWithMFATokenProviderRequest::new(
-                role, key, secret, mfa_serial,
-            ))
-        } else {
-            Err("Failed to find a profile or source")
+        role: Option<RoleStep>,
+    ) -> Result<Self, String> {
+        let mut visited: Vec<&str> = vec![];
+        let mut chain = vec![];
+        let mut name = profile_name;
+
+        let base = loop {
+            let profile = set
+                .get_profile(name)
+                .ok_or_else(|| format!("Failed to find profile {}", name))?;
+
+            if visited.contains(&name) {
+                return Err(format!(
+                    "Found a source_profile loop {} -> {}",
+                    visited.join(" -> "),
+                    name
+                ));
+            }
+
+            visited.push(name);
+
+            // After the first profile, static keys take priority over assuming another role
+            if visited.len() > 1 {
+                if let Some(base) = Self::static_credentials(profile) {
+                    break base;
+                }
+            }
+
+            let step = match RoleStep::from_profile(profile)? {
+                Some(step) => step,
+                None if visited.len() == 1
+                    && role.is_none()
+                    && profile.get("web_identity_token_file").is_none() =>
+                {
+                    return Err("Failed to find a role to assume in selected profile".to_string())
+                }
+                None => break Self::base_credentials(profile)?,
+            };
+
+            chain.push(step);
+
+            match (
+                profile.get("source_profile"),
+                profile.get("credential_source"),
+            ) {
+                (Some(_), Some(_)) => {
+                    return Err(format!(
+                        "Profile {} sets both source_profile and credential_source",
+                        name
+                    ))
+                }
+                (Some(source), None) if source == name => {
+                    break Self::static_credentials(profile).ok_or_else(|| {
+                        format!("Failed to find an access key in profile {}", name)
+                    })?
+                }
+                (Some(source), None) => name = source,
+                (None, Some(source)) => break BaseCredentials::Source(source.to_string()),
+                (None, None) => {
+                    return Err(format!(
+                        "Profile {} must set a source_profile or credential_source",
+                        name
+                    ))
+                }
+            }
+        };
+
+        chain.reverse();
+
+        if let Some(mut role) = role {
+            if chain.is_empty() && role.mfa_serial.is_none() {
+                role.mfa_serial = set
+                    .get_profile(profile_name)
+                    .and_then(|p| p.get("mfa_serial"))
+                    .map(|s| s.to_string());
+            }
+
+            chain.push(role);
         }
+
+        if chain.iter().all(|step| step.mfa_serial.is_none()) {
+            return Err("Failed to find a mfa serial to use in selected profile".to_string());
+        }
+
+        Ok(AssumeRoleWithMFATokenProviderRequest { base, chain })
     }
 
-    fn get_source_profile<'a>(profile: &str, set: &'a ProfileSet) -> Option<&'a Profile> {
-        set.get_profile(profile).and_then(|p| {
-            if let Some(source) = p.get("source_profile") {
-                Self::get_source_profile(source, set)
-            } else {
-                Some(p)
-            }
-        })
+    /// The role that is assumed last, and whose credentials are returned
+    fn role(&self) -> &str {
+        self.chain
+            .last()
+            .map(|step| step.role.as_str())
+            .unwrap_or("")
     }
 
-    fn extract_field<'a, const N: usize>(
-        field: &str,
-        profiles: &[&'a Profile; N],
-    ) -> Option<&'a str> {
-        for profile in profiles {
-            if let Some(value) = profile.get(field) {
-                return Some(value);
-            }
+    fn static_credentials(profile: &Profile) -> Option<BaseCredentials> {
+        match (
+            profile.get("aws_access_key_id"),
+            profile.get("aws_secret_access_key"),
+        ) {
+            (Some(key), Some(secret)) => Some(BaseCredentials::Static {
+                key: key.to_string(),
+                secret: secret.to_string(),
+                session_token: profile.get("aws_session_token").map(|s| s.to_string()),
+            }),
+            _ => None,
+        }
+    }
+
+    fn base_credentials(profile: &Profile) -> Result<BaseCredentials, String> {
+        if let Some(source) = profile.get("credential_source") {
+            Ok(BaseCredentials::Source(source.to_string()))
+        } else if profile.get("web_identity_token_file").is_some() {
+            Ok(BaseCredentials::WebIdentity(profile.name().to_string()))
+        } else if let Some(provider) = SsoCredentialsProvider::from_profile(profile)? {
+            Ok(BaseCredentials::Sso(provider))
+        } else {
+            Self::static_credentials(profile).ok_or_else(|| {
+                format!(
+                    "Failed to find an access key in source profile {}",
+                    profile.name()
+                )
+            })
         }
+    }
+}
 
-        None
+impl AssumeRoleWithMFATokenProvider {
+    async fn mfa_token(&self, first: bool) -> Result<String, CredentialsError> {
+        let source = if first || self.token_source.is_reusable() {
+            self.token_source.clone()
+        } else {
+            TokenSource::Prompt
+        };
+
+        tokio::task::spawn_blocking(move || source.token())
+            .await
+            .map_err(CredentialsError::not_loaded)?
+            .map_err(CredentialsError::not_loaded)
     }
 }
 
+/// Converts the credentials returned by STS, which may be missing fields
+fn into_credentials(
+    role: &str,
+    credentials: Option<aws_sdk_sts::model::Credentials>,
+) -> Result<Credentials, CredentialsError> {
+    let missing = |field: &str| {
+        CredentialsError::unhandled(format!("Assuming {} did not return {}", role, field))
+    };
+
+    let credentials = credentials.ok_or_else(|| missing("credentials"))?;
+
+    let invalid = |reason: String| {
+        CredentialsError::unhandled(format!(
+            "Assuming {} returned an invalid expiration: {}",
+            role, reason
+        ))
+    };
+
+    // Expirations before 1970 cannot come from STS, so they are treated like ones that do not
+    // fit in a SystemTime
+    let expiration = credentials
+        .expiration
+        .map(|t| match SystemTime::try_from(t) {
+            Ok(time) if time >= UNIX_EPOCH => Ok(time),
+            Ok(_) => Err(invalid(format!("{} is before 1970", t.secs()))),
+            Err(err) => Err(invalid(err.to_string())),
+        })
+        .transpose()?;
+
+    Ok(Credentials::new(
+        credentials
+            .access_key_id
+            .ok_or_else(|| missing("an access key"))?,
+        credentials
+            .secret_access_key
+            .ok_or_else(|| missing("a secret key"))?,
+        credentials.session_token,
+        expiration,
+        "AssumeRoleWithMFAToken",
+    ))
+}
+
 impl ProvideCredentials for AssumeRoleWithMFATokenProvider {
     #[instrument]
     fn provide_credentials<'a>(&'a self) -> aws_types::credentials::future::ProvideCredentials<'a>
@@ -119,102 +384,348 @@ impl ProvideCredentials for AssumeRoleWithMFATokenProvider {
                 &aws_types::os_shim_internal::Env::default(),
             )
             .await
-            .map_err(|err| CredentialsError::not_loaded(err))?;
+            .map_err(CredentialsError::not_loaded)?;
 
-            let profile_name = self
-                .profile
-                .as_ref()
-                .map(|s| s.as_str())
-                .unwrap_or("default");
+            let profile_name = self.profile.as_deref().unwrap_or("default");
 
-            let request =
-                AssumeRoleWithMFATokenProviderRequest::from_profile_set(profile_name, &profiles)
-                    .await
-                    .map_err(|err| CredentialsError::not_loaded(err))?;
-
-            let region = DefaultRegionChain::builder()
-                .profile_name(
-                    self.profile
-                        .as_ref()
-                        .map(|s| s.as_str())
-                        .unwrap_or("default"),
-                )
-                .build()
-                .region()
-                .await;
-
-            let credentials_provider =
-                SharedCredentialsProvider::new(aws_types::credentials::Credentials::new(
-                    request.key,
-                    request.secret,
-                    None,
-                    None,
-                    "assumed-role-credentials",
-                ));
+            let request = AssumeRoleWithMFATokenProviderRequest::from_profile_set(
+                profile_name,
+                &profiles,
+                self.role.clone(),
+            )
+            .await
+            .map_err(CredentialsError::not_loaded)?;
 
-            let config = aws_config::Config::builder()
-                .region(region.clone())
-                .credentials_provider(SharedCredentialsProvider::new(credentials_provider))
-                .build();
-
-            let sts_client = aws_sdk_sts::Client::new(&config);
-
-            // TODO: Academically how do we rewrite this block to prevent creating a copy of the
-            // token when it has already been supplied
-            let mfa_token = if let Some(mfa_token) = &self.token {
-                mfa_token.to_string()
-            } else {
-                let handle = tokio::task::spawn_blocking(|| -> Result<String, CredentialsError> {
-                    print!("MFA token is required: ");
-                    std::io::stdout()
-                        .flush()
-                        .map_err(|err| CredentialsError::not_loaded(err))?;
-
-                    let mut input = String::new();
-                    std::io::stdin()
-                        .read_line(&mut input)
-                        .map_err(|err| CredentialsError::not_loaded(err))?;
-                    Ok(input.trim().to_string())
-                })
-                .await
-                .map_err(|err| CredentialsError::not_loaded(err))?;
+            if let Some(ref cache) = self.cache {
+                if let Some(credentials) = cache.load(profile_name, request.role()) {
+                    return Ok(credentials);
+                }
+            }
 
-                handle?
+            let region = match self.region {
+                Some(ref region) => Some(region.clone()),
+                None => {
+                    DefaultRegionChain::builder()
+                        .profile_name(profile_name)
+                        .build()
+                        .region()
+                        .await
+                }
             };
 
-            let role = sts_client
-                .assume_role()
-                .role_session_name("envfmt")
-                .role_arn(request.role)
-                .serial_number(request.mfa_serial)
-                .token_code(mfa_token)
-                .send()
-                .await
-                .map_err(|err| CredentialsError::not_loaded(err))?;
-
-            if let Some(creds) = role.credentials {
-                Ok(aws_types::credentials::Credentials::new(
-                    creds
-                        .access_key_id
-                        .as_ref()
-                        .expect("Returned credentials is missing an access key"),
-                    creds
-                        .secret_access_key
-                        .as_ref()
-                        .expect("Returned credentials is missing a secret key"),
-                    creds.session_token().map(|s| s.into()),
-                    creds.expiration.map(|t| {
-                        SystemTime::try_from(t)
-                            .expect("Failed to convert expiration for session token")
-                    }),
-                    // credentials.expiration().map(|t| t.into()),
-                    "AssumeRoleWithMFAToken",
-                ))
-            } else {
-                Err(CredentialsError::not_loaded(
-                    "Successfully assume role, but not credentials were returned",
-                ))
+            let mut credentials_provider = request
+                .base
+                .provider()
+                .map_err(CredentialsError::not_loaded)?;
+            let mut credentials = None;
+            let mut first_token = true;
+
+            for step in request.chain.iter() {
+                let config = aws_config::Config::builder()
+                    .region(region.clone())
+                    .credentials_provider(credentials_provider.clone())
+                    .build();
+
+                let sts_client = aws_sdk_sts::Client::new(&config);
+
+                let mfa_token = match step.mfa_serial {
+                    Some(_) => {
+                        let token = self.mfa_token(first_token).await?;
+                        first_token = false;
+                        Some(token)
+                    }
+                    None => None,
+                };
+
+                let role = sts_client
+                    .assume_role()
+                    .role_session_name(step.session_name.as_deref().unwrap_or("envfmt"))
+                    .role_arn(&step.role)
+                    .set_external_id(step.external_id.clone())
+                    .set_duration_seconds(step.duration_seconds)
+                    .set_serial_number(step.mfa_serial.clone())
+                    .set_token_code(mfa_token)
+                    .send()
+                    .await
+                    .map_err(CredentialsError::not_loaded)?;
+
+                let assumed = into_credentials(&step.role, role.credentials)?;
+
+                credentials_provider = SharedCredentialsProvider::new(assumed.clone());
+                credentials = Some(assumed);
+            }
+
+            let credentials = credentials.ok_or_else(|| {
+                CredentialsError::not_loaded("Selected profile does not assume any roles")
+            })?;
+
+            if let Some(ref cache) = self.cache {
+                if let Err(err) = cache.store(profile_name, request.role(), &credentials) {
+                    warn!(%err, "Failed to cache assumed role credentials");
+                }
             }
+
+            Ok(credentials)
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use aws_types::os_shim_internal::{Env, Fs};
+
+    use super::*;
+
+    async fn profiles(config: &str, credentials: &str) -> ProfileSet {
+        aws_config::profile::load(
+            &Fs::from_slice(&[("config", config), ("credentials", credentials)]),
+            &Env::from_slice(&[
+                ("AWS_CONFIG_FILE", "config"),
+                ("AWS_SHARED_CREDENTIALS_FILE", "credentials"),
+            ]),
+        )
+        .await
+        .unwrap()
+    }
+
+    fn step(role: &str) -> RoleStep {
+        RoleStep {
+            role: role.to_string(),
+            external_id: None,
+            session_name: None,
+            duration_seconds: None,
+            mfa_serial: None,
+        }
+    }
+
+    fn step_with_mfa(role: &str) -> RoleStep {
+        RoleStep {
+            mfa_serial: Some("arn:mfa".to_string()),
+            ..step(role)
+        }
+    }
+
+    fn static_keys(key: &str) -> BaseCredentials {
+        BaseCredentials::Static {
+            key: key.to_string(),
+            secret: "secret".to_string(),
+            session_token: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reads_role_settings() {
+        let set = profiles(
+            "[profile admin]\nrole_arn = arn:role/admin\nsource_profile = base\nmfa_serial = arn:mfa\nexternal_id = ext\nrole_session_name = session\nduration_seconds = 900\n",
+            "[base]\naws_access_key_id = key\naws_secret_access_key = secret\n",
+        )
+        .await;
+
+        let request = AssumeRoleWithMFATokenProviderRequest::from_profile_set("admin", &set, None)
+            .await
+            .unwrap();
+
+        assert_eq!(static_keys("key"), request.base);
+        assert_eq!(
+            vec![RoleStep {
+                role: "arn:role/admin".to_string(),
+                external_id: Some("ext".to_string()),
+                session_name: Some("session".to_string()),
+                duration_seconds: Some(900),
+                mfa_serial: Some("arn:mfa".to_string()),
+            }],
+            request.chain
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chains_roles_through_source_profiles() {
+        let set = profiles(
+            "[profile admin]\nrole_arn = arn:role/admin\nsource_profile = middle\n\n[profile middle]\nrole_arn = arn:role/middle\nsource_profile = base\nmfa_serial = arn:mfa\n",
+            "[base]\naws_access_key_id = key\naws_secret_access_key = secret\n",
+        )
+        .await;
+
+        let request = AssumeRoleWithMFATokenProviderRequest::from_profile_set("admin", &set, None)
+            .await
+            .unwrap();
+
+        assert_eq!(static_keys("key"), request.base);
+        assert_eq!(
+            vec!["arn:role/middle", "arn:role/admin"],
+            request
+                .chain
+                .iter()
+                .map(|s| s.role.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!("arn:role/admin", request.role());
+    }
+
+    #[tokio::test]
+    async fn test_static_keys_end_the_chain() {
+        let set = profiles(
+            "[profile admin]\nrole_arn = arn:role/admin\nsource_profile = middle\nmfa_serial = arn:mfa\n\n[profile middle]\nrole_arn = arn:role/middle\nsource_profile = base\n",
+            "[middle]\naws_access_key_id = middle_key\naws_secret_access_key = secret\n",
+        )
+        .await;
+
+        let request = AssumeRoleWithMFATokenProviderRequest::from_profile_set("admin", &set, None)
+            .await
+            .unwrap();
+
+        assert_eq!(static_keys("middle_key"), request.base);
+        assert_eq!(vec![step_with_mfa("arn:role/admin")], request.chain);
+    }
+
+    #[tokio::test]
+    async fn test_supports_credential_source_and_self_reference() {
+        let set = profiles(
+            "[profile ec2]\nrole_arn = arn:role/ec2\ncredential_source = Ec2InstanceMetadata\nmfa_serial = arn:mfa\n\n[profile self]\nrole_arn = arn:role/self\nsource_profile = self\nmfa_serial = arn:mfa\n",
+            "[self]\naws_access_key_id = self_key\naws_secret_access_key = secret\n",
+        )
+        .await;
+
+        let ec2 = AssumeRoleWithMFATokenProviderRequest::from_profile_set("ec2", &set, None)
+            .await
+            .unwrap();
+        let own = AssumeRoleWithMFATokenProviderRequest::from_profile_set("self", &set, None)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            BaseCredentials::Source("Ec2InstanceMetadata".to_string()),
+            ec2.base
+        );
+        assert_eq!(static_keys("self_key"), own.base);
+    }
+
+    #[tokio::test]
+    async fn test_detects_sso_and_web_identity_sources() {
+        let set = profiles(
+            "[profile sso]\nsso_start_url = https://example.awsapps.com/start\nsso_region = eu-west-1\nsso_account_id = 123456789012\nsso_role_name = Developer\n\n[profile web]\nrole_arn = arn:role/web\nweb_identity_token_file = /token\n\n[profile from-sso]\nrole_arn = arn:role/a\nsource_profile = sso\nmfa_serial = arn:mfa\n\n[profile from-web]\nrole_arn = arn:role/b\nsource_profile = web\nmfa_serial = arn:mfa\n",
+            "",
+        )
+        .await;
+
+        let sso = AssumeRoleWithMFATokenProviderRequest::from_profile_set("from-sso", &set, None)
+            .await
+            .unwrap();
+        let web = AssumeRoleWithMFATokenProviderRequest::from_profile_set("from-web", &set, None)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            BaseCredentials::Sso(
+                SsoCredentialsProvider::from_profile(set.get_profile("sso").unwrap())
+                    .unwrap()
+                    .unwrap()
+            ),
+            sso.base
+        );
+        assert_eq!(BaseCredentials::WebIdentity("web".to_string()), web.base);
+    }
+
+    #[tokio::test]
+    async fn test_assumes_explicit_role_last() {
+        let set = profiles(
+            "[profile admin]\nrole_arn = arn:role/admin\nsource_profile = base\nmfa_serial = arn:mfa\n",
+            "[base]\naws_access_key_id = key\naws_secret_access_key = secret\n\n[user]\naws_access_key_id = user_key\naws_secret_access_key = secret\nmfa_serial = arn:mfa\n",
+        )
+        .await;
+        let role = RoleStep::new("arn:role/target", Some("ext".to_string()));
+
+        let chained = AssumeRoleWithMFATokenProviderRequest::from_profile_set(
+            "admin",
+            &set,
+            Some(role.clone()),
+        )
+        .await
+        .unwrap();
+        let direct = AssumeRoleWithMFATokenProviderRequest::from_profile_set(
+            "user",
+            &set,
+            Some(role.clone()),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            vec![step_with_mfa("arn:role/admin"), role.clone()],
+            chained.chain
+        );
+        assert_eq!("arn:role/target", chained.role());
+        assert_eq!(static_keys("user_key"), direct.base);
+        assert_eq!(
+            vec![RoleStep {
+                mfa_serial: Some("arn:mfa".to_string()),
+                ..role
+            }],
+            direct.chain
+        );
+    }
+
+    #[test]
+    fn test_reports_incomplete_sts_credentials() {
+        let complete = aws_sdk_sts::model::Credentials::builder()
+            .access_key_id("key")
+            .secret_access_key("secret")
+            .session_token("token")
+            .build();
+
+        let credentials = into_credentials("arn:role/a", Some(complete.clone())).unwrap();
+        assert_eq!("key", credentials.access_key_id());
+        assert_eq!(Some("token"), credentials.session_token());
+
+        let mut no_key = complete.clone();
+        no_key.access_key_id = None;
+        let mut no_secret = complete;
+        no_secret.secret_access_key = None;
+
+        for (credentials, message) in [
+            (None, "did not return credentials"),
+            (Some(no_key), "did not return an access key"),
+            (Some(no_secret), "did not return a secret key"),
+        ] {
+            let err = into_credentials("arn:role/a", credentials).unwrap_err();
+            assert!(err.to_string().contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_reports_invalid_expiration() {
+        let credentials = aws_sdk_sts::model::Credentials::builder()
+            .access_key_id("key")
+            .secret_access_key("secret")
+            .expiration(aws_smithy_types::DateTime::from_secs(-1))
+            .build();
+
+        let err = into_credentials("arn:role/a", Some(credentials)).unwrap_err();
+        assert!(matches!(err, CredentialsError::Unhandled { .. }));
+        assert!(
+            err.to_string()
+                .contains("Assuming arn:role/a returned an invalid expiration"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejects_invalid_chains() {
+        let set = profiles(
+            "[profile a]\nrole_arn = arn:role/a\nsource_profile = b\nmfa_serial = arn:mfa\n\n[profile b]\nrole_arn = arn:role/b\nsource_profile = a\n\n[profile no-mfa]\nrole_arn = arn:role/c\ncredential_source = Environment\n\n[profile no-role]\nregion = us-east-1\n",
+            "",
+        )
+        .await;
+
+        for name in ["a", "no-mfa", "no-role", "missing"] {
+            assert!(
+                AssumeRoleWithMFATokenProviderRequest::from_profile_set(name, &set, None)
+                    .await
+                    .is_err(),
+                "{} should not resolve",
+                name
+            );
+        }
+    }
+}
@@ -1,24 +1,41 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
+use http::Uri;
 
+use std::error::Error;
 use std::fmt;
 use std::str::FromStr;
 
+use crate::history::Timestamp;
+use crate::params::ParamFilter;
+use crate::parser::{InputFormat, ParseMode};
+use crate::tags::Tag;
+use crate::token::TOKEN_ENV_VAR;
+use crate::transform::{parse_rename_map, Case, KeyTransform, ListStyle, NameStrategy};
+use crate::writer::{Policies, Tier};
+
 #[derive(Debug, Parser)]
 #[clap(author, version, about)]
 pub struct EnvFmtOpts {
     // Mode to operate in. read or write
     #[clap(subcommand)]
     pub command: Command,
-    #[clap(name = "format", long, short, global = true, help ="Format to use when printing results", possible_values = ["dot-env", "php-fpm"])]
+    #[clap(name = "format", long, short, global = true, help ="Format to use when printing results", possible_values = ["dot-env", "php-fpm", "json", "yaml"])]
     pub format: Option<Format>,
     #[clap(
         name = "region",
         long,
         short,
-        help = "AWS region to query against. Defaults to us-east-1",
+        help = "AWS region to query against. Defaults to the region of the profile or environment",
         global = true
     )]
     pub region: Option<String>,
+    #[clap(
+        name = "endpoint-url",
+        long,
+        help = "Send Parameter Store requests to this URL instead of the AWS endpoint, e.g. a local emulator",
+        global = true
+    )]
+    pub endpoint_url: Option<Uri>,
     #[clap(
         name = "profile",
         long,
@@ -29,6 +46,47 @@ pub struct EnvFmtOpts {
     pub profile: Option<String>,
     #[clap(name = "debug", long, help = "Display verbose debug information")]
     pub debug: bool,
+    #[clap(
+        name = "quiet",
+        long,
+        short,
+        help = "Only display errors",
+        global = true,
+        conflicts_with = "verbose"
+    )]
+    pub quiet: bool,
+    #[clap(
+        name = "verbose",
+        long,
+        short,
+        help = "Display additional progress information",
+        global = true
+    )]
+    pub verbose: bool,
+    #[clap(
+        name = "role-arn",
+        long,
+        help = "Role to assume after authenticating with the profile",
+        global = true
+    )]
+    pub role_arn: Option<String>,
+    #[clap(
+        name = "external-id",
+        long,
+        help = "External id to pass when assuming --role-arn",
+        global = true,
+        requires = "role-arn"
+    )]
+    pub external_id: Option<String>,
+    #[clap(
+        name = "web-identity-token-file",
+        long,
+        help = "Assume --role-arn with the OIDC token in this file",
+        global = true,
+        requires = "role-arn",
+        conflicts_with_all = &["external-id", "mfa", "mfa-token", "mfa-command", "mfa-totp-file", "mfa-totp-keyring"]
+    )]
+    pub web_identity_token_file: Option<String>,
     #[clap(
         name = "mfa",
         long,
@@ -42,17 +100,67 @@ pub struct EnvFmtOpts {
         long,
         help = "Enables MFA authentication and accepts token instead of prompting",
         global = true,
-        conflicts_with = "mfa"
+        conflicts_with_all = &["mfa", "mfa-command", "mfa-totp-file", "mfa-totp-keyring"]
     )]
     pub mfa_token: Option<String>,
+    #[clap(
+        name = "mfa-command",
+        long,
+        help = "Enables MFA authentication and runs this command to get a token",
+        global = true,
+        conflicts_with_all = &["mfa-token", "mfa-totp-file", "mfa-totp-keyring"]
+    )]
+    pub mfa_command: Option<String>,
+    #[clap(
+        name = "mfa-totp-file",
+        long,
+        help = "Enables MFA authentication and generates tokens from the TOTP secret in this file",
+        global = true,
+        conflicts_with_all = &["mfa-token", "mfa-command", "mfa-totp-keyring"]
+    )]
+    pub mfa_totp_file: Option<String>,
+    #[clap(
+        name = "mfa-totp-keyring",
+        long,
+        help = "Enables MFA authentication and generates tokens from the TOTP secret stored in the OS keyring under this service. Supported on macOS and Linux",
+        global = true,
+        conflicts_with_all = &["mfa-token", "mfa-command", "mfa-totp-file"]
+    )]
+    pub mfa_totp_keyring: Option<String>,
+    #[clap(
+        name = "no-cache",
+        long,
+        help = "Always request new MFA credentials instead of reusing cached ones",
+        global = true
+    )]
+    pub no_cache: bool,
     #[clap(
         name = "out",
         short,
         long,
-        help = "Output location for parameters instead of stdout",
-        global = true
+        help = "Output location for parameters instead of stdout"
     )]
     pub out: Option<String>,
+    #[clap(
+        name = "mask",
+        long,
+        help = "Print ******** in place of every value, e.g. to show config in CI logs",
+        global = true
+    )]
+    pub mask: bool,
+}
+
+impl EnvFmtOpts {
+    /// Whether to assume credentials with an MFA token. Setting `ENVFMT_MFA_TOKEN` is enough,
+    /// unless a web identity token is used instead.
+    pub fn uses_mfa(&self) -> bool {
+        self.mfa
+            || self.mfa_token.is_some()
+            || self.mfa_command.is_some()
+            || self.mfa_totp_file.is_some()
+            || self.mfa_totp_keyring.is_some()
+            || (self.web_identity_token_file.is_none() && std::env::var_os(TOKEN_ENV_VAR).is_some())
+    }
 }
 
 #[derive(Debug, Subcommand)]
@@ -61,23 +169,304 @@ pub enum Command {
     Read {
         /// Path prefix to select parameters for
         path: String,
+        /// Only keep keys matching this pattern. May be repeated
+        #[clap(long)]
+        include: Vec<String>,
+        /// Drop keys matching this pattern. May be repeated
+        #[clap(long)]
+        exclude: Vec<String>,
+        /// Treat include and exclude patterns as regular expressions instead of globs
+        #[clap(long)]
+        regex: bool,
+        /// Server side filter in the form Key=Type,Option=Equals,Values=SecureString. May be repeated
+        #[clap(long)]
+        filter: Vec<ParamFilter>,
+        #[clap(flatten)]
+        transform: TransformOpts,
+        /// How to handle keys that are not valid environment variable names
+        #[clap(long, default_value = "sanitize", possible_values = ["sanitize", "skip", "fail"])]
+        invalid_names: NameStrategy,
+        /// Expand ${VAR} references in values using other parameters and the environment
+        #[clap(long)]
+        expand: bool,
+        /// Resolve ssm:<name> and secretsmanager:<id>[#field] values to what they reference
+        #[clap(long)]
+        resolve_refs: bool,
+        /// Precede each value with a comment giving its full path, type, version and last
+        /// modified date
+        #[clap(long)]
+        metadata: bool,
+        /// Read the values as they were at this time, e.g. 2021-06-30T17:00:00Z. Parameters
+        /// deleted since are not brought back, as SSM drops their history
+        #[clap(long)]
+        at: Option<Timestamp>,
+        /// Read the version of each parameter carrying this label, skipping those without it
+        #[clap(long, conflicts_with = "at")]
+        label: Option<String>,
+        /// Only read parameters carrying this tag, e.g. service=api. May be repeated
+        #[clap(long)]
+        tag: Vec<Tag>,
+        /// Output StringList values joined by commas, as a JSON array, or expanded to one key
+        /// per item named KEY_0, KEY_1 and so on
+        #[clap(long, default_value = "join", possible_values = ["join", "json", "expand"])]
+        string_lists: ListStyle,
+        /// Output location for parameters instead of stdout
+        #[clap(short, long)]
+        out: Option<String>,
     },
     /// Write parameters to AWS
     Write {
         #[clap(long, help = "Prefix to prepend tp each variable")]
         prefix: Option<String>,
-        /// File path to a config file to read from
+        /// File path to a config file to read from, or - for stdin
+        file_path: String,
+        /// Format of the file, picked from its extension or contents if not given
+        #[clap(long, possible_values = ["dot-env", "php-fpm", "json", "yaml", "properties"])]
+        input_format: Option<InputFormat>,
+        /// Allow overwriting of existing values
+        #[clap(short, long)]
+        overwrite: bool,
+        #[clap(flatten)]
+        transform: TransformOpts,
+        /// Print a summary of the parameters written to stdout in this format
+        #[clap(long, default_value = "text", possible_values = ["text", "json"])]
+        output: OutputFormat,
+        /// Fail on malformed lines and duplicate keys (strict) or warn and skip them (lenient)
+        #[clap(long, default_value = "strict", possible_values = ["strict", "lenient"])]
+        parse_mode: ParseMode,
+        /// Tag to attach to each parameter, e.g. owner=platform. May be repeated
+        #[clap(long)]
+        tag: Vec<Tag>,
+        /// Description to store with each parameter
+        #[clap(long)]
+        description: Option<String>,
+        #[clap(flatten)]
+        storage: StorageOpts,
+        /// Key whose comma separated value is written as a StringList. May be repeated
+        #[clap(long)]
+        list: Vec<String>,
+    },
+    /// List the parameters below a path with their type, version and last modified date
+    Ls {
+        /// Path to list parameters for
+        path: String,
+        /// Also list parameters below sub paths
+        #[clap(long)]
+        recursive: bool,
+        /// Print the (decrypted) value of each parameter
+        #[clap(long)]
+        show_values: bool,
+        /// Print the parameters as a JSON list instead of a tree
+        #[clap(long)]
+        json: bool,
+    },
+    /// Print the (decrypted) value of a single parameter
+    Get {
+        /// Full name of the parameter. The key is named like the keys written by write
+        name: String,
+        #[clap(flatten)]
+        transform: TransformOpts,
+    },
+    /// Write a single parameter
+    Set {
+        /// Full name of the parameter. The key is named like the keys written by write
+        name: String,
+        /// The value, or - to read it from stdin. Prompted for when not given
+        #[clap(conflicts_with = "from-file")]
+        value: Option<String>,
+        /// Read the value from a file
+        #[clap(long)]
+        from_file: Option<String>,
+        /// Store the value as a SecureString
+        #[clap(long)]
+        secure: bool,
+        /// Description to store with the parameter
+        #[clap(long)]
+        description: Option<String>,
+        /// Allow overwriting an existing value
+        #[clap(long)]
+        overwrite: bool,
+        #[clap(flatten)]
+        transform: TransformOpts,
+        /// Tag to attach to the parameter, e.g. owner=platform. May be repeated
+        #[clap(long)]
+        tag: Vec<Tag>,
+        #[clap(flatten)]
+        storage: StorageOpts,
+        /// Store the comma separated value as a StringList
+        #[clap(long, conflicts_with = "secure")]
+        list: bool,
+    },
+    /// Delete a parameter, or every parameter below a path
+    Rm {
+        /// Full name of the parameter or path
+        name: String,
+        /// Delete every parameter below the path, after listing them and asking for confirmation
+        #[clap(long)]
+        recursive: bool,
+        /// Save the names, types, values and tags of the parameters to this new JSON file before
+        /// deleting. The file is readable only by you and is never overwritten.
+        #[clap(long)]
+        backup: Option<String>,
+        /// Do not ask for confirmation
+        #[clap(long)]
+        yes: bool,
+    },
+    /// Save the names, types, values and tags of every parameter below a path to a new JSON file
+    /// that restore can write back. The file is readable only by you and is never overwritten.
+    Export {
+        /// Path to export parameters below
+        path: String,
+        /// File path to save the parameters to
         file_path: String,
+    },
+    /// Copy every parameter below a path to the same names below another, with its type and tags
+    Copy {
+        /// Path to copy parameters from
+        from: String,
+        /// Path to copy parameters to
+        to: String,
         /// Allow overwriting of existing values
         #[clap(short, long)]
         overwrite: bool,
     },
+    /// Write back the parameters saved by export or rm --backup under their own names, types
+    /// and tags
+    Restore {
+        /// File path to a file saved by export or rm --backup
+        file_path: String,
+        /// Allow overwriting of existing values
+        #[clap(short, long)]
+        overwrite: bool,
+    },
+    /// List the versions of a parameter with when and by whom they were stored
+    History {
+        /// Full name of the parameter
+        name: String,
+        /// Print the (decrypted) value of each version
+        #[clap(long)]
+        show_values: bool,
+        /// Print the versions as text or json
+        #[clap(long, default_value = "text", possible_values = ["text", "json"])]
+        output: OutputFormat,
+    },
+    /// Store an earlier value of a parameter again as its newest version
+    Rollback {
+        /// Full name of the parameter
+        name: String,
+        /// The version to restore
+        #[clap(long)]
+        to_version: i64,
+    },
+    /// Label the newest version of every parameter directly below a path
+    Label {
+        /// Path prefix to select parameters for
+        path: String,
+        /// The label to move to the newest versions
+        #[clap(long)]
+        label: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = ArgError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(ArgError::InvalidFormat),
+        }
+    }
+}
+
+#[derive(Debug, Default, Args)]
+pub struct TransformOpts {
+    /// Remove this prefix from each key when present
+    #[clap(long)]
+    pub strip_prefix: Option<String>,
+    /// Add this prefix to each key
+    #[clap(long)]
+    pub add_prefix: Option<String>,
+    /// Case style for keys. Defaults to upper when reading and lower when writing
+    #[clap(long, possible_values = ["upper", "lower", "preserve"])]
+    pub case: Option<Case>,
+    /// Replace - and . in keys with _
+    #[clap(long)]
+    pub replace_separators: bool,
+    /// File of FROM=TO lines renaming individual keys, from Parameter Store names to local keys
+    #[clap(long)]
+    pub rename_map: Option<String>,
+}
+
+impl TransformOpts {
+    pub fn to_transform(&self, default_case: Case) -> Result<KeyTransform, Box<dyn Error>> {
+        let renames = match self.rename_map {
+            Some(ref path) => parse_rename_map(&std::fs::read_to_string(path)?)?,
+            None => Default::default(),
+        };
+
+        Ok(KeyTransform {
+            strip_prefix: self.strip_prefix.clone(),
+            add_prefix: self.add_prefix.clone(),
+            replace_separators: self.replace_separators,
+            case: self.case.unwrap_or(default_case),
+            renames,
+        })
+    }
+}
+
+#[derive(Debug, Default, Args)]
+pub struct StorageOpts {
+    /// Tier to store values in. Values over 4KB or with policies are stored as advanced unless
+    /// given
+    #[clap(long, possible_values = ["standard", "advanced", "intelligent-tiering"])]
+    pub tier: Option<Tier>,
+    /// Delete the values at this time, e.g. 2021-06-30T17:00:00Z
+    #[clap(long)]
+    pub expires_at: Option<Timestamp>,
+    /// Send an EventBridge notification this many days before the values expire
+    #[clap(long, requires = "expires-at")]
+    pub notify_before_expiry: Option<u32>,
+    /// Send an EventBridge notification when the values have not changed for this many days
+    #[clap(long)]
+    pub notify_unchanged_after: Option<u32>,
+}
+
+impl StorageOpts {
+    pub fn to_policies(&self) -> Policies {
+        Policies {
+            expires_at: self.expires_at,
+            notify_before_expiry: self.notify_before_expiry,
+            notify_unchanged_after: self.notify_unchanged_after,
+        }
+    }
 }
 
 impl Default for Command {
     fn default() -> Command {
         Command::Read {
             path: "".to_string(),
+            include: vec![],
+            exclude: vec![],
+            regex: false,
+            filter: vec![],
+            transform: TransformOpts::default(),
+            invalid_names: NameStrategy::Sanitize,
+            expand: false,
+            resolve_refs: false,
+            metadata: false,
+            at: None,
+            label: None,
+            tag: vec![],
+            string_lists: ListStyle::Join,
+            out: None,
         }
     }
 }
@@ -86,16 +475,22 @@ impl Default for Command {
 pub enum Format {
     DotEnv,
     PhpFpm,
+    Json,
+    Yaml,
 }
 
 #[derive(Debug)]
 pub enum ArgError {
     InvalidFormat,
+    InvalidFilter(String),
 }
 
 impl fmt::Display for ArgError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} is not a valid output format", self)
+        match self {
+            ArgError::InvalidFormat => write!(f, "Not a valid output format"),
+            ArgError::InvalidFilter(reason) => write!(f, "Not a valid filter: {}", reason),
+        }
     }
 }
 
@@ -106,9 +501,153 @@ impl FromStr for Format {
         match s {
             "dot-env" => Ok(Format::DotEnv),
             "php-fpm" => Ok(Format::PhpFpm),
+            "json" => Ok(Format::Json),
+            "yaml" => Ok(Format::Yaml),
             _ => Err(ArgError::InvalidFormat),
         }
     }
 }
 
+/// Parses the shorthand used by the AWS CLI for `--parameter-filters`. Bare items following
+/// `Values=` are treated as additional values, so `Key=Label,Values=a,b` selects both labels.
+impl FromStr for ParamFilter {
+    type Err = ArgError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut key = None;
+        let mut option = None;
+        let mut values = vec![];
+
+        for item in s.split(',') {
+            match item.split_once('=') {
+                Some(("Key", k)) => key = Some(k.to_string()),
+                Some(("Option", o)) => option = Some(o.to_string()),
+                Some(("Values", v)) => values.push(v.to_string()),
+                _ if !values.is_empty() => values.push(item.to_string()),
+                _ => return Err(ArgError::InvalidFilter(format!("unexpected item {}", item))),
+            }
+        }
+
+        let key = key.ok_or_else(|| ArgError::InvalidFilter("missing Key".to_string()))?;
+
+        if values.is_empty() {
+            return Err(ArgError::InvalidFilter("missing Values".to_string()));
+        }
+
+        Ok(ParamFilter {
+            key,
+            option,
+            values,
+        })
+    }
+}
+
 impl std::error::Error for ArgError {}
+
+#[cfg(test)]
+mod tests {
+    use clap::IntoApp;
+
+    use super::*;
+
+    #[test]
+    fn test_command_line_definition_is_valid() {
+        EnvFmtOpts::command().debug_assert();
+    }
+
+    #[test]
+    fn test_keeps_short_flags_for_out_and_overwrite() {
+        for args in [
+            vec!["envfmt", "-o", ".env", "read", "/app"],
+            vec!["envfmt", "read", "/app", "-o", ".env"],
+        ] {
+            let opts = EnvFmtOpts::try_parse_from(args).unwrap();
+
+            match opts.command {
+                Command::Read { out, .. } => assert_eq!(Some(".env"), out.or(opts.out).as_deref()),
+                other => panic!("Expected read, found {:?}", other),
+            }
+        }
+
+        let opts = EnvFmtOpts::try_parse_from(["envfmt", "write", ".env", "-o"]).unwrap();
+
+        match opts.command {
+            Command::Write { overwrite, .. } => assert!(overwrite),
+            other => panic!("Expected write, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_get_and_set_take_key_transforms() {
+        let opts = EnvFmtOpts::try_parse_from(["envfmt", "set", "/app/x", "1", "--case", "upper"])
+            .unwrap();
+
+        match opts.command {
+            Command::Set { transform, .. } => assert_eq!(Some(Case::Upper), transform.case),
+            other => panic!("Expected set, found {:?}", other),
+        }
+
+        let opts =
+            EnvFmtOpts::try_parse_from(["envfmt", "get", "/app/x", "--case", "preserve"]).unwrap();
+
+        match opts.command {
+            Command::Get { transform, .. } => assert_eq!(Some(Case::Preserve), transform.case),
+            other => panic!("Expected get, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_rejects_more_than_one_token_source() {
+        let sources = [
+            ["--mfa-token", "123456"],
+            ["--mfa-command", "ykman"],
+            ["--mfa-totp-file", "totp"],
+            ["--mfa-totp-keyring", "aws"],
+        ];
+
+        for (i, first) in sources.iter().enumerate() {
+            let args = ["envfmt", "ls", "/app", first[0], first[1]];
+            assert!(EnvFmtOpts::try_parse_from(args).is_ok());
+
+            for second in sources.iter().skip(i + 1) {
+                let args = [
+                    "envfmt", "ls", "/app", first[0], first[1], second[0], second[1],
+                ];
+                assert!(EnvFmtOpts::try_parse_from(args).is_err(), "{:?}", args);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parses_filter_shorthand() {
+        let filter = "Key=Type,Option=Equals,Values=SecureString"
+            .parse::<ParamFilter>()
+            .unwrap();
+
+        assert_eq!(
+            ParamFilter {
+                key: "Type".to_string(),
+                option: Some("Equals".to_string()),
+                values: vec!["SecureString".to_string()],
+            },
+            filter
+        );
+    }
+
+    #[test]
+    fn test_parses_filter_with_multiple_values() {
+        let filter = "Key=Label,Values=stable,canary"
+            .parse::<ParamFilter>()
+            .unwrap();
+
+        assert_eq!(None, filter.option);
+        assert_eq!(vec!["stable", "canary"], filter.values);
+    }
+
+    #[test]
+    fn test_rejects_incomplete_filters() {
+        assert!("Values=a".parse::<ParamFilter>().is_err());
+        assert!("Key=Type".parse::<ParamFilter>().is_err());
+        assert!("Type=String".parse::<ParamFilter>().is_err());
+    }
+}
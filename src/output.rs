@@ -0,0 +1,108 @@
+//! Human facing messages. Everything here is written to stderr so that stdout only ever carries
+//! formatted parameters or machine readable summaries.
+
+use aws_types::credentials::{future, CredentialsError, ProvideCredentials};
+
+use std::fmt::Display;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Verbosity {
+    Quiet = 0,
+    Normal = 1,
+    Verbose = 2,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+impl Verbosity {
+    pub fn from_flags(quiet: bool, verbose: bool) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, true) => Verbosity::Verbose,
+            (false, false) => Verbosity::Normal,
+        }
+    }
+}
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+fn enabled(verbosity: Verbosity) -> bool {
+    VERBOSITY.load(Ordering::Relaxed) >= verbosity as u8
+}
+
+/// Errors are always shown
+pub fn error(message: impl Display) {
+    eprintln!("error: {}", message);
+}
+
+pub fn warning(message: impl Display) {
+    if enabled(Verbosity::Normal) {
+        eprintln!("warning: {}", message);
+    }
+}
+
+/// Progress of an operation, e.g. each parameter written
+pub fn status(message: impl Display) {
+    if enabled(Verbosity::Normal) {
+        eprintln!("{}", message);
+    }
+}
+
+/// Additional detail only shown with `--verbose`
+pub fn verbose(message: impl Display) {
+    if enabled(Verbosity::Verbose) {
+        eprintln!("{}", message);
+    }
+}
+
+/// Asks a yes or no question on the terminal. Without a terminal to ask on the answer is no, so
+/// piped input can never confirm anything.
+pub fn confirm(question: impl Display) -> bool {
+    let mut tty = match OpenOptions::new().read(true).write(true).open("/dev/tty") {
+        Ok(tty) => tty,
+        Err(_) => return false,
+    };
+
+    if write!(tty, "{} [y/N] ", question)
+        .and_then(|_| tty.flush())
+        .is_err()
+    {
+        return false;
+    }
+
+    let mut answer = String::new();
+
+    match BufReader::new(tty).read_line(&mut answer) {
+        Ok(_) => matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"),
+        Err(_) => false,
+    }
+}
+
+/// Reports why credentials could not be loaded. The SDK ignores `CredentialsNotLoaded` and then
+/// fails with "No credentials in the property bag", so without this the reason, e.g. an expired
+/// SSO login, is lost. Other errors are part of the request error and are not reported again.
+#[derive(Debug)]
+pub struct ReportCredentialErrors<P>(pub P);
+
+impl<P> ProvideCredentials for ReportCredentialErrors<P>
+where
+    P: ProvideCredentials,
+{
+    fn provide_credentials<'a>(&'a self) -> future::ProvideCredentials<'a>
+    where
+        Self: 'a,
+    {
+        future::ProvideCredentials::new(async move {
+            let result = self.0.provide_credentials().await;
+            if let Err(err @ CredentialsError::CredentialsNotLoaded { .. }) = &result {
+                error(err);
+            }
+            result
+        })
+    }
+}
@@ -1,8 +1,18 @@
 use async_trait::async_trait;
-use dotenv::from_filename_iter;
+use aws_sdk_ssm::model::{Parameter, ParameterStringFilter, ParameterType};
+use aws_smithy_types::date_time::Format;
+use aws_smithy_types::DateTime;
 use tracing::debug;
 
 use std::error::Error;
+use std::fmt;
+use std::io::Read;
+
+use crate::output;
+use crate::parser::{InputFormat, ParseError, ParseMode};
+use crate::secret::{Secret, MASK};
+use crate::tags::Tag;
+use crate::transform::KeyTransform;
 
 #[async_trait]
 pub trait ReadParamClient {
@@ -16,17 +26,26 @@ impl ReadParamClient for aws_sdk_ssm::Client {
             .get_parameters_by_path()
             .path(&bag.prefix)
             .set_next_token(bag.next)
+            .recursive(bag.recursive)
+            .with_decryption(bag.decrypt)
+            .set_parameter_filters(if bag.filters.is_empty() {
+                None
+            } else {
+                Some(
+                    bag.filters
+                        .iter()
+                        .map(ParameterStringFilter::from)
+                        .collect(),
+                )
+            })
             .send()
             .await
             .map_err(Box::new)?;
 
         if let Some(parameters) = resp.parameters {
             for parameter in parameters {
-                if let (Some(name), Some(value)) = (parameter.name, parameter.value) {
-                    bag.params.push(Param {
-                        key: to_env_name(name.as_str()).to_string(),
-                        value,
-                    });
+                if let Some(param) = Param::from_parameter(parameter, &bag.transform) {
+                    bag.params.push(param);
                 }
             }
         }
@@ -40,7 +59,100 @@ impl ReadParamClient for aws_sdk_ssm::Client {
 #[derive(Debug, PartialEq)]
 pub struct Param {
     pub key: String,
-    pub value: String,
+    pub value: Secret,
+    /// Only set for parameters read from SSM
+    pub meta: Option<ParamMeta>,
+}
+
+impl Param {
+    /// Converts a parameter returned by SSM, keeping everything but the selector and source
+    pub fn from_parameter(parameter: Parameter, transform: &KeyTransform) -> Option<Self> {
+        let name = parameter.name?;
+
+        Some(Param {
+            key: to_env_name(&name, transform),
+            value: parameter.value?.into(),
+            meta: Some(ParamMeta {
+                name,
+                param_type: parameter.r#type,
+                version: parameter.version,
+                arn: parameter.arn,
+                data_type: parameter.data_type,
+                last_modified: parameter.last_modified_date,
+                tags: vec![],
+            }),
+        })
+    }
+
+    /// Whether SSM stores the value as a comma separated `StringList`
+    pub fn is_list(&self) -> bool {
+        matches!(
+            &self.meta,
+            Some(ParamMeta {
+                param_type: Some(ParameterType::StringList),
+                ..
+            })
+        )
+    }
+}
+
+/// What SSM reports about a parameter besides its value
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamMeta {
+    /// The full path of the parameter before it was turned into a key
+    pub name: String,
+    pub param_type: Option<ParameterType>,
+    pub version: i64,
+    pub arn: Option<String>,
+    /// `text` unless the parameter holds e.g. an `aws:ec2:image` id
+    pub data_type: Option<String>,
+    pub last_modified: Option<DateTime>,
+    /// Only fetched for `export`, `copy` and `rm --backup`
+    pub tags: Vec<Tag>,
+}
+
+impl fmt::Display for ParamMeta {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+
+        if let Some(param_type) = &self.param_type {
+            write!(f, " {}", param_type.as_str())?;
+        }
+
+        if let Some(data_type) = self.data_type.as_deref().filter(|t| *t != "text") {
+            write!(f, " ({})", data_type)?;
+        }
+
+        write!(f, " version {}", self.version)?;
+
+        if let Some(modified) = self
+            .last_modified
+            .as_ref()
+            .and_then(|t| t.fmt(Format::DateTime).ok())
+        {
+            write!(f, " modified {}", modified)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A server side filter passed along to SSM as a `ParameterStringFilter`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamFilter {
+    pub key: String,
+    pub option: Option<String>,
+    pub values: Vec<String>,
+}
+
+impl From<&ParamFilter> for ParameterStringFilter {
+    fn from(filter: &ParamFilter) -> Self {
+        ParameterStringFilter::builder()
+            .key(&filter.key)
+            .set_option(filter.option.clone())
+            .set_values(Some(filter.values.clone()))
+            .build()
+    }
 }
 
 #[derive(Debug)]
@@ -48,6 +160,12 @@ pub struct ParamBag {
     pub prefix: String,
     pub params: Vec<Param>,
     pub next: Option<String>,
+    pub filters: Vec<ParamFilter>,
+    pub transform: KeyTransform,
+    /// Also read parameters below sub paths of the prefix
+    pub recursive: bool,
+    /// Return the plain values of `SecureString` parameters
+    pub decrypt: bool,
 }
 
 impl ParamBag {
@@ -59,24 +177,125 @@ impl ParamBag {
             prefix: path_formatted,
             params: Vec::new(),
             next: None,
+            filters: Vec::new(),
+            transform: KeyTransform::default(),
+            recursive: false,
+            decrypt: false,
         }
     }
 
-    pub fn from_dotenv(file: &str, prefix: &str) -> Self {
-        let params = from_filename_iter(&file)
-            .unwrap()
-            .filter_map(|item| item.ok())
-            .map(|(key, value)| Param { key, value })
-            .collect::<Vec<Param>>();
+    pub fn with_transform(mut self, transform: KeyTransform) -> Self {
+        self.transform = transform;
+        self
+    }
 
-        ParamBag {
+    pub fn with_filters(mut self, filters: Vec<ParamFilter>) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    pub fn with_recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    pub fn with_decryption(mut self, decrypt: bool) -> Self {
+        self.decrypt = decrypt;
+        self
+    }
+
+    /// Replaces every value with a mask, keeping the keys and metadata
+    pub fn mask(&mut self) {
+        for param in self.params.iter_mut() {
+            param.value = MASK.into();
+        }
+    }
+
+    /// Reads a local file, or stdin if `file` is `-`. Without an explicit format it is picked
+    /// from the file extension or guessed from the contents. In strict mode any line that cannot
+    /// be used is an error, otherwise such lines are reported as warnings and skipped.
+    pub fn from_input(
+        file: &str,
+        prefix: &str,
+        format: Option<InputFormat>,
+        mode: ParseMode,
+    ) -> Result<Self, InputError> {
+        let (file, contents) = if file == "-" {
+            let mut contents = String::new();
+            let read = std::io::stdin().read_to_string(&mut contents);
+            ("stdin", read.map(|_| contents))
+        } else {
+            (file, std::fs::read_to_string(file))
+        };
+        let contents = contents.map_err(|source| InputError::Read {
+            file: file.to_string(),
+            source,
+        })?;
+
+        let format = format
+            .or_else(|| InputFormat::from_path(file))
+            .unwrap_or_else(|| InputFormat::detect(&contents));
+        let parsed = format.parse(&contents);
+
+        if !parsed.errors.is_empty() {
+            match mode {
+                ParseMode::Strict => {
+                    return Err(InputError::Parse {
+                        file: file.to_string(),
+                        errors: parsed.errors,
+                    })
+                }
+                ParseMode::Lenient => {
+                    for err in parsed.errors {
+                        output::warning(format!("{}:{}: {}", file, err.line, err.kind));
+                    }
+                }
+            }
+        }
+
+        Ok(ParamBag {
             prefix: prefix.to_string(),
-            params,
+            params: parsed.params,
             next: None,
+            filters: Vec::new(),
+            transform: KeyTransform::default(),
+            recursive: false,
+            decrypt: false,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum InputError {
+    Read {
+        file: String,
+        source: std::io::Error,
+    },
+    Parse {
+        file: String,
+        errors: Vec<ParseError>,
+    },
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InputError::Read { file, source } => write!(f, "Failed to read {}: {}", file, source),
+            InputError::Parse { file, errors } => {
+                write!(f, "Failed to parse {}", file)?;
+
+                for err in errors {
+                    write!(f, "\n  {}:{}: {}", file, err.line, err.kind)?;
+                }
+
+                Ok(())
+            }
         }
     }
 }
 
+impl Error for InputError {}
+
 pub fn normalize_path(path: &str) -> String {
     match path.chars().next() {
         Some('/') => path.to_string(),
@@ -96,8 +315,8 @@ impl ParamBag {
     }
 }
 
-pub fn to_env_name(name: &str) -> String {
-    name[name.rfind('/').unwrap_or(0) + 1..].to_uppercase()
+pub fn to_env_name(name: &str, transform: &KeyTransform) -> String {
+    transform.apply(&name[name.rfind('/').unwrap_or(0) + 1..])
 }
 
 #[tracing::instrument(skip(client))]
@@ -105,8 +324,16 @@ pub async fn get_all_params_for_path<T>(client: &T, path: &str) -> ParamResult
 where
     T: ReadParamClient,
 {
-    let mut bag = ParamBag::new(path);
+    get_all_params(client, ParamBag::new(path)).await
+}
 
+/// Pages through all of the parameters for an already constructed bag, allowing the caller to
+/// configure the bag (e.g. with filters) before the first request is made
+#[tracing::instrument(skip(client))]
+pub async fn get_all_params<T>(client: &T, mut bag: ParamBag) -> ParamResult
+where
+    T: ReadParamClient,
+{
     debug!(?bag, "Created empty bag for storage bag");
 
     loop {
@@ -123,16 +350,58 @@ where
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use std::{collections::HashMap, sync::RwLock};
 
     use async_trait::async_trait;
     use serde::Deserialize;
-    use serde_json;
     use tracing::instrument;
 
     use super::*;
 
+    /// A param as read from a local file, without metadata
+    pub fn param(key: &str, value: &str) -> Param {
+        Param {
+            key: key.to_string(),
+            value: value.into(),
+            meta: None,
+        }
+    }
+
+    /// A bag below `/app` holding params read from a local file
+    pub fn bag(params: &[(&str, &str)]) -> ParamBag {
+        let mut bag = ParamBag::new("/app");
+        bag.params = params
+            .iter()
+            .map(|(key, value)| param(key, value))
+            .collect();
+        bag
+    }
+
+    /// Metadata of the first version of a `String` parameter
+    pub fn meta(name: &str) -> ParamMeta {
+        ParamMeta {
+            name: name.to_string(),
+            param_type: Some(ParameterType::String),
+            version: 1,
+            arn: None,
+            data_type: None,
+            last_modified: None,
+            tags: vec![],
+        }
+    }
+
+    /// A bag below `/app` holding params read from SSM, keyed by their names
+    pub fn ssm_bag(params: &[(&str, &str)]) -> ParamBag {
+        let mut bag = bag(params);
+
+        for param in bag.params.iter_mut() {
+            param.meta = Some(meta(&param.key));
+        }
+
+        bag
+    }
+
     #[derive(Deserialize)]
     struct TestParam {
         key: String,
@@ -171,8 +440,9 @@ mod tests {
             if let Some(page) = page {
                 for p in &page.params {
                     bag.params.push(Param {
-                        key: to_env_name(&p.key).to_string(),
-                        value: p.value.clone(),
+                        key: to_env_name(&p.key, &bag.transform),
+                        value: p.value.clone().into(),
+                        meta: None,
                     });
                 }
 
@@ -222,38 +492,18 @@ mod tests {
 
     #[tokio::test]
     async fn test_process_add_results_to_bag() {
-        let mut bag = ParamBag {
-            prefix: "/path/to/the/".to_string(),
-            params: vec![],
-            next: None,
-        };
+        let mut bag = bag(&[]);
 
         bag = bag.process(&one_page_client()).await.unwrap();
 
-        assert_eq!(
-            Param {
-                key: "FIRST_PARAM".into(),
-                value: "first_param_value".into()
-            },
-            bag.params[0]
-        );
+        assert_eq!(param("FIRST_PARAM", "first_param_value"), bag.params[0]);
 
-        assert_eq!(
-            Param {
-                key: "SECOND_PARAM".into(),
-                value: "second_param_value".into()
-            },
-            bag.params[1]
-        );
+        assert_eq!(param("SECOND_PARAM", "second_param_value"), bag.params[1]);
     }
 
     #[tokio::test]
     async fn test_process_updates_with_next_token() {
-        let mut bag = ParamBag {
-            prefix: "/path/to/the/".to_string(),
-            params: vec![],
-            next: None,
-        };
+        let mut bag = bag(&[]);
 
         bag = bag.process(&two_page_client()).await.unwrap();
 
@@ -263,11 +513,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_process_updates_with_empty_token() {
-        let mut bag = ParamBag {
-            prefix: "/path/to/the/".to_string(),
-            params: vec![],
-            next: None,
-        };
+        let mut bag = bag(&[]);
 
         let client = two_page_client();
 
@@ -279,7 +525,10 @@ mod tests {
 
     #[test]
     fn test_converts_to_env_var_name() {
-        assert_eq!("PARAM_KEY", to_env_name("/path/to/the/param_key"));
+        assert_eq!(
+            "PARAM_KEY",
+            to_env_name("/path/to/the/param_key", &KeyTransform::default())
+        );
     }
 
     #[tokio::test]
@@ -299,4 +548,68 @@ mod tests {
         assert_eq!(4, bag.params.len());
         assert!(bag.next.is_none());
     }
+
+    #[test]
+    fn test_reports_missing_dotenv_file() {
+        match ParamBag::from_input("test_data/missing.env", "/app", None, ParseMode::Strict) {
+            Err(InputError::Read { file, .. }) => assert_eq!("test_data/missing.env", file),
+            other => panic!("Expected read error, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reads_dotenv_in_strict_and_lenient_modes() {
+        match ParamBag::from_input("test_data/malformed.env", "/app", None, ParseMode::Strict) {
+            Err(InputError::Parse { errors, .. }) => {
+                assert_eq!(
+                    vec![3, 5],
+                    errors.iter().map(|e| e.line).collect::<Vec<_>>()
+                )
+            }
+            other => panic!("Expected parse error, found {:?}", other),
+        }
+
+        let bag = ParamBag::from_input("test_data/malformed.env", "/app", None, ParseMode::Lenient)
+            .unwrap();
+
+        assert_eq!(
+            vec!["DATABASE_URL", "API_KEY"],
+            bag.params
+                .iter()
+                .map(|p| p.key.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!("second", bag.params[1].value);
+    }
+
+    #[test]
+    fn test_keeps_parameter_metadata() {
+        let parameter = Parameter::builder()
+            .name("/app/prod/db_host")
+            .value("db.internal")
+            .r#type(ParameterType::SecureString)
+            .version(3)
+            .arn("arn:aws:ssm:eu-west-1:123456789012:parameter/app/prod/db_host")
+            .data_type("text")
+            .last_modified_date(DateTime::from_secs(1_600_000_000))
+            .build();
+
+        let param = Param::from_parameter(parameter, &KeyTransform::default()).unwrap();
+        let meta = param.meta.unwrap();
+
+        assert_eq!("/app/prod/db_host", meta.name);
+        assert_eq!(3, meta.version);
+        assert_eq!(
+            "/app/prod/db_host SecureString version 3 modified 2020-09-13T12:26:40Z",
+            meta.to_string()
+        );
+
+        assert_eq!(
+            None,
+            Param::from_parameter(
+                Parameter::builder().name("/app/no_value").build(),
+                &KeyTransform::default()
+            )
+        );
+    }
 }
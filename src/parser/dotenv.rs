@@ -0,0 +1,213 @@
+use super::{lines, ParseErrorKind, Parsed};
+
+/// Parses `KEY=value` lines in the format written by `DotEnv`. Lines may start with `export`,
+/// values may be single quoted (literal) or double quoted (with `\` escapes) and quoted values
+/// may span several lines. Values are never interpolated.
+pub fn parse_dotenv(input: &str) -> Parsed {
+    let lines = lines(input);
+    let mut parsed = Parsed::default();
+    let mut index = 0;
+
+    while index < lines.len() {
+        let line = index + 1;
+
+        match parse_entry(&lines, &mut index) {
+            Ok(Some((key, value))) => parsed.push(line, key, value),
+            Ok(None) => {}
+            Err(kind) => parsed.error(line, kind),
+        }
+
+        index += 1;
+    }
+
+    parsed
+}
+
+/// Parses the entry starting at `lines[*index]`, leaving `index` on the last line it used
+fn parse_entry(
+    lines: &[&str],
+    index: &mut usize,
+) -> Result<Option<(String, String)>, ParseErrorKind> {
+    let line = lines[*index].trim_start();
+
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    let line = match line.strip_prefix("export") {
+        Some(rest) if rest.starts_with(char::is_whitespace) => rest.trim_start(),
+        _ => line,
+    };
+
+    let (key, rest) = line.split_once('=').ok_or(ParseErrorKind::MissingEquals)?;
+    let key = key.trim_end();
+
+    let rest = rest.trim_start();
+
+    let value = match rest.chars().next() {
+        Some(quote @ '"') | Some(quote @ '\'') => {
+            let (value, after) = parse_quoted(lines, index, &rest[1..], quote)?;
+            let after = after.trim();
+
+            if !after.is_empty() && !after.starts_with('#') {
+                return Err(ParseErrorKind::TrailingCharacters(after.chars().count()));
+            }
+
+            value
+        }
+        _ => match rest.find(" #").or_else(|| rest.find("\t#")) {
+            Some(comment) => rest[..comment].trim_end().to_string(),
+            None => rest.trim_end().to_string(),
+        },
+    };
+
+    Ok(Some((key.to_string(), value)))
+}
+
+/// Reads a quoted value, continuing onto following lines until the closing quote. Returns the
+/// value and whatever follows the closing quote.
+pub(super) fn parse_quoted<'a>(
+    lines: &[&'a str],
+    index: &mut usize,
+    first: &'a str,
+    quote: char,
+) -> Result<(String, &'a str), ParseErrorKind> {
+    let mut value = String::new();
+    let mut current = first;
+
+    loop {
+        let mut chars = current.char_indices();
+
+        while let Some((i, c)) = chars.next() {
+            match c {
+                c if c == quote => return Ok((value, &current[i + c.len_utf8()..])),
+                '\\' if quote == '"' => match chars.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, 'r')) => value.push('\r'),
+                    Some((_, escaped @ ('\\' | '"' | '\'' | '$'))) => value.push(escaped),
+                    Some((_, other)) => {
+                        value.push('\\');
+                        value.push(other);
+                    }
+                    None => value.push('\\'),
+                },
+                c => value.push(c),
+            }
+        }
+
+        *index += 1;
+
+        // The rest of the file is part of the value, so it is not parsed any further
+        if *index >= lines.len() {
+            *index = lines.len() - 1;
+            return Err(ParseErrorKind::Unterminated);
+        }
+
+        value.push('\n');
+        current = lines[*index];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatter::DotEnv;
+    use crate::params::tests::bag;
+    use crate::parser::tests::pairs;
+    use crate::parser::ParseError;
+
+    #[test]
+    fn test_parses_dotenv_lines() {
+        let parsed = parse_dotenv(
+            "# comment\n\nPLAIN=value # trailing\nexport EXPORTED=\"quoted\"\nSINGLE='no \\n escapes'\nESCAPED=\"a \\\"b\\\" \\\\ \\n\"\nEMPTY=\nSPACED = spaced value\r\n",
+        );
+
+        assert_eq!(Vec::<ParseError>::new(), parsed.errors);
+        assert_eq!(
+            vec![
+                ("PLAIN", "value"),
+                ("EXPORTED", "quoted"),
+                ("SINGLE", "no \\n escapes"),
+                ("ESCAPED", "a \"b\" \\ \n"),
+                ("EMPTY", ""),
+                ("SPACED", "spaced value"),
+            ],
+            pairs(&parsed)
+        );
+    }
+
+    #[test]
+    fn test_parses_multi_line_values() {
+        let parsed = parse_dotenv("KEY=\"-----BEGIN-----\nabc\n-----END-----\"\nNEXT=1\n");
+
+        assert_eq!(
+            vec![
+                ("KEY", "-----BEGIN-----\nabc\n-----END-----"),
+                ("NEXT", "1")
+            ],
+            pairs(&parsed)
+        );
+    }
+
+    #[test]
+    fn test_reports_line_numbers() {
+        let parsed =
+            parse_dotenv("GOOD=1\nnot a pair\n1BAD=2\nQUOTED=\"a\" b\nOPEN=\"never\nclosed\n");
+
+        assert_eq!(vec![("GOOD", "1")], pairs(&parsed));
+        assert_eq!(
+            vec![
+                ParseError {
+                    line: 2,
+                    kind: ParseErrorKind::MissingEquals
+                },
+                ParseError {
+                    line: 3,
+                    kind: ParseErrorKind::InvalidKey("1BAD".to_string())
+                },
+                ParseError {
+                    line: 4,
+                    kind: ParseErrorKind::TrailingCharacters(1)
+                },
+                ParseError {
+                    line: 5,
+                    kind: ParseErrorKind::Unterminated
+                },
+            ],
+            parsed.errors
+        );
+    }
+
+    #[test]
+    fn test_detects_duplicate_keys() {
+        let parsed = parse_dotenv("KEY=first\nOTHER=x\nKEY=second\n");
+
+        assert_eq!(vec![("OTHER", "x"), ("KEY", "second")], pairs(&parsed));
+        assert_eq!(
+            vec![ParseError {
+                line: 3,
+                kind: ParseErrorKind::DuplicateKey {
+                    key: "KEY".to_string(),
+                    first_line: 1
+                }
+            }],
+            parsed.errors
+        );
+    }
+
+    #[test]
+    fn test_reads_what_dotenv_writes() {
+        let bag = bag(&[
+            ("PLAIN", "value"),
+            ("QUOTES", "say \"hi\""),
+            ("BACKSLASH", "C:\\path\\n"),
+            ("MULTI", "line one\nline two"),
+            ("DOLLAR", "pa$$word"),
+        ]);
+
+        let parsed = parse_dotenv(&DotEnv::from(&bag).to_string());
+
+        assert_eq!(Vec::<ParseError>::new(), parsed.errors);
+        assert_eq!(bag.params, parsed.params);
+    }
+}
@@ -0,0 +1,181 @@
+use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
+use serde_json::error::Category;
+use serde_json::Value;
+
+use std::fmt;
+
+use super::{ParseErrorKind, Parsed};
+
+/// The entries of a JSON object in file order, including repeated keys which `Value` would
+/// silently collapse
+struct Entries(Vec<(String, Value)>);
+
+impl<'de> Deserialize<'de> for Entries {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(EntriesVisitor)
+    }
+}
+
+struct EntriesVisitor;
+
+impl<'de> Visitor<'de> for EntriesVisitor {
+    type Value = Entries;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an object of keys and values")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Entries, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut entries = vec![];
+
+        while let Some(entry) = map.next_entry::<String, Value>()? {
+            entries.push(entry);
+        }
+
+        Ok(Entries(entries))
+    }
+}
+
+/// Parses a flat JSON object. Strings are used as is, numbers and booleans as written and
+/// `null` as an empty value. Nested objects and arrays are reported. Numbers keep their text,
+/// e.g. `1.10` stays `1.10`, as `serde_json` is built with `arbitrary_precision`. The one
+/// exception is `-0`, which reads as `0`.
+pub fn parse_json(input: &str) -> Parsed {
+    let mut parsed = Parsed::default();
+
+    let entries = match serde_json::from_str::<Entries>(input) {
+        Ok(Entries(entries)) => entries,
+        Err(err) => {
+            parsed.error(err.line().max(1), syntax_error(&err));
+            return parsed;
+        }
+    };
+
+    let mut cursor = 0;
+
+    for (key, value) in entries {
+        let line = line_of_key(input, &key, &mut cursor);
+
+        match value {
+            Value::String(value) => parsed.push(line, key, value),
+            Value::Null => parsed.push(line, key, String::new()),
+            Value::Bool(_) | Value::Number(_) => parsed.push(line, key, value.to_string()),
+            Value::Array(_) | Value::Object(_) => {
+                parsed.error(line, ParseErrorKind::UnsupportedValue(key))
+            }
+        }
+    }
+
+    parsed
+}
+
+/// Describes why a file is not a JSON object by its kind and column only, as the messages of
+/// `serde_json` quote the values they trip over
+fn syntax_error(err: &serde_json::Error) -> ParseErrorKind {
+    let kind = match err.classify() {
+        Category::Io => "could not read JSON",
+        Category::Syntax => "invalid JSON",
+        Category::Data => "expected an object of keys and values",
+        Category::Eof => "JSON ends early",
+    };
+
+    ParseErrorKind::Syntax(format!("{} at column {}", kind, err.column()))
+}
+
+/// Finds the line a key was written on by searching forward from the previous key, as
+/// `serde_json` does not keep positions
+fn line_of_key(input: &str, key: &str, cursor: &mut usize) -> usize {
+    let quoted = serde_json::to_string(key).unwrap_or_default();
+
+    if let Some(offset) = input[*cursor..].find(&quoted) {
+        *cursor += offset + quoted.len();
+    }
+
+    input[..*cursor].matches('\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tests::pairs;
+    use crate::parser::ParseError;
+
+    #[test]
+    fn test_parses_flat_objects() {
+        let parsed = parse_json(
+            "{\n  \"HOST\": \"db\",\n  \"PORT\": 5432,\n  \"DEBUG\": false,\n  \"EMPTY\": null\n}",
+        );
+
+        assert_eq!(Vec::<ParseError>::new(), parsed.errors);
+        assert_eq!(
+            vec![
+                ("HOST", "db"),
+                ("PORT", "5432"),
+                ("DEBUG", "false"),
+                ("EMPTY", "")
+            ],
+            pairs(&parsed)
+        );
+    }
+
+    #[test]
+    fn test_keeps_numbers_as_written() {
+        let parsed = parse_json(
+            "{\"A\": 1.10, \"B\": 1e3, \"C\": -0.0, \"D\": 12345678901234567890123, \"E\": 2E-3}",
+        );
+
+        assert_eq!(
+            vec![
+                ("A", "1.10"),
+                ("B", "1e3"),
+                ("C", "-0.0"),
+                ("D", "12345678901234567890123"),
+                ("E", "2E-3")
+            ],
+            pairs(&parsed)
+        );
+    }
+
+    #[test]
+    fn test_reports_nested_and_repeated_keys() {
+        let parsed = parse_json("{\n\"A\": 1,\n\"NESTED\": {\"B\": 2},\n\"A\": 3\n}");
+
+        assert_eq!(vec![("A", "3")], pairs(&parsed));
+        assert_eq!(
+            vec![
+                ParseError {
+                    line: 3,
+                    kind: ParseErrorKind::UnsupportedValue("NESTED".to_string())
+                },
+                ParseError {
+                    line: 4,
+                    kind: ParseErrorKind::DuplicateKey {
+                        key: "A".to_string(),
+                        first_line: 2
+                    }
+                },
+            ],
+            parsed.errors
+        );
+    }
+
+    #[test]
+    fn test_reports_invalid_json() {
+        let parsed = parse_json("{\n\"A\": 1,\n}");
+        assert_eq!(3, parsed.errors[0].line);
+
+        assert_eq!(
+            "line 3: invalid JSON at column 1",
+            parsed.errors[0].to_string()
+        );
+
+        let parsed = parse_json("[1, 2]");
+        assert!(matches!(parsed.errors[0].kind, ParseErrorKind::Syntax(_)));
+    }
+}
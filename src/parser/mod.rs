@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::opt::Format;
+use crate::params::Param;
+
+mod dotenv;
+mod json;
+mod php_fpm;
+mod properties;
+mod yaml;
+
+pub use self::dotenv::parse_dotenv;
+pub use self::json::parse_json;
+pub use self::php_fpm::parse_php_fpm;
+pub use self::properties::parse_properties;
+pub use self::yaml::parse_yaml;
+
+/// How to handle lines of a local file that cannot be used
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParseMode {
+    /// Fail on the first problem
+    Strict,
+    /// Warn about problems and upload the remaining parameters
+    Lenient,
+}
+
+impl FromStr for ParseMode {
+    type Err = ParseErrorKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(ParseMode::Strict),
+            "lenient" => Ok(ParseMode::Lenient),
+            _ => Err(ParseErrorKind::UnknownMode(s.to_string())),
+        }
+    }
+}
+
+/// The formats that `write` can read parameters from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputFormat {
+    DotEnv,
+    PhpFpm,
+    Json,
+    Yaml,
+    Properties,
+}
+
+impl FromStr for InputFormat {
+    type Err = ParseErrorKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot-env" => Ok(InputFormat::DotEnv),
+            "php-fpm" => Ok(InputFormat::PhpFpm),
+            "json" => Ok(InputFormat::Json),
+            "yaml" => Ok(InputFormat::Yaml),
+            "properties" => Ok(InputFormat::Properties),
+            _ => Err(ParseErrorKind::UnknownFormat(s.to_string())),
+        }
+    }
+}
+
+impl InputFormat {
+    /// Picks a format from a file extension, e.g. `app.json` or `pool.d/env.conf`
+    pub fn from_path(path: &str) -> Option<Self> {
+        match Path::new(path).extension()?.to_str()? {
+            "env" => Some(InputFormat::DotEnv),
+            "conf" => Some(InputFormat::PhpFpm),
+            "json" => Some(InputFormat::Json),
+            "yaml" | "yml" => Some(InputFormat::Yaml),
+            "properties" => Some(InputFormat::Properties),
+            _ => None,
+        }
+    }
+
+    /// Guesses the format of input without a telling extension, such as stdin or `.env.local`.
+    /// Only JSON and php-fpm can be told apart reliably, anything else is read as dotenv.
+    pub fn detect(input: &str) -> Self {
+        let first = input
+            .lines()
+            .map(|line| line.trim())
+            .find(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with(';'));
+
+        match first {
+            Some(line) if line.starts_with('{') => InputFormat::Json,
+            Some(line) if line.starts_with("env[") || line.starts_with('[') => InputFormat::PhpFpm,
+            _ => InputFormat::DotEnv,
+        }
+    }
+
+    pub fn parse(&self, input: &str) -> Parsed {
+        match self {
+            InputFormat::DotEnv => parse_dotenv(input),
+            InputFormat::PhpFpm => parse_php_fpm(input),
+            InputFormat::Json => parse_json(input),
+            InputFormat::Yaml => parse_yaml(input),
+            InputFormat::Properties => parse_properties(input),
+        }
+    }
+}
+
+/// Everything `read` prints can be read back by `write`
+impl From<&Format> for InputFormat {
+    fn from(format: &Format) -> Self {
+        match format {
+            Format::DotEnv => InputFormat::DotEnv,
+            Format::PhpFpm => InputFormat::PhpFpm,
+            Format::Json => InputFormat::Json,
+            Format::Yaml => InputFormat::Yaml,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+    UnknownMode(String),
+    UnknownFormat(String),
+    InvalidKey(String),
+    MissingEquals,
+    Unterminated,
+    /// How many characters follow the closing quote, as they may be part of a value
+    TrailingCharacters(usize),
+    DuplicateKey {
+        key: String,
+        first_line: usize,
+    },
+    Syntax(String),
+    UnsupportedValue(String),
+    /// A number or boolean that cannot be read without changing how it is written
+    UnquotedNumber(String),
+}
+
+/// A problem with a single line, numbered from 1
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseErrorKind::UnknownMode(mode) => write!(f, "Unknown parse mode {}", mode),
+            ParseErrorKind::UnknownFormat(format) => write!(f, "Unknown input format {}", format),
+            ParseErrorKind::InvalidKey(key) => write!(f, "{} is not a valid key", key),
+            ParseErrorKind::MissingEquals => write!(f, "expected KEY=value"),
+            ParseErrorKind::Unterminated => write!(f, "quoted value is never closed"),
+            ParseErrorKind::TrailingCharacters(count) => {
+                write!(f, "{} unexpected characters after quoted value", count)
+            }
+            ParseErrorKind::DuplicateKey { key, first_line } => {
+                write!(f, "{} is already set on line {}", key, first_line)
+            }
+            ParseErrorKind::Syntax(message) => write!(f, "{}", message),
+            ParseErrorKind::UnsupportedValue(key) => write!(
+                f,
+                "{} is not a plain value, nested values and lists are not supported",
+                key
+            ),
+            ParseErrorKind::UnquotedNumber(key) => write!(
+                f,
+                "{} would not be kept as written, quote its value to store it as is",
+                key
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl std::error::Error for ParseErrorKind {}
+
+impl std::error::Error for ParseError {}
+
+/// The parameters read from a file along with any lines that could not be used
+#[derive(Debug, Default, PartialEq)]
+pub struct Parsed {
+    pub params: Vec<Param>,
+    pub errors: Vec<ParseError>,
+    seen: HashMap<String, usize>,
+}
+
+impl Parsed {
+    /// Adds a parameter read from a line. Invalid keys are reported, and a repeated key
+    /// replaces the earlier value and is reported.
+    fn push(&mut self, line: usize, key: String, value: String) {
+        if !is_valid_key(&key) {
+            return self.error(line, ParseErrorKind::InvalidKey(key));
+        }
+
+        if let Some(first_line) = self.seen.insert(key.clone(), line) {
+            self.error(
+                line,
+                ParseErrorKind::DuplicateKey {
+                    key: key.clone(),
+                    first_line,
+                },
+            );
+            self.params.retain(|param| param.key != key);
+        }
+
+        self.params.push(Param {
+            key,
+            value: value.into(),
+            meta: None,
+        });
+    }
+
+    fn error(&mut self, line: usize, kind: ParseErrorKind) {
+        self.errors.push(ParseError { line, kind });
+    }
+}
+
+/// Splits input into lines, accepting both `\n` and `\r\n` endings
+fn lines(input: &str) -> Vec<&str> {
+    input
+        .split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .collect()
+}
+
+/// Keys start with a letter or underscore followed by letters, digits, `_`, `.` or `-`
+fn is_valid_key(key: &str) -> bool {
+    let mut chars = key.chars();
+
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub fn pairs(parsed: &Parsed) -> Vec<(&str, &str)> {
+        parsed
+            .params
+            .iter()
+            .map(|p| (p.key.as_str(), p.value.expose()))
+            .collect()
+    }
+
+    #[test]
+    fn test_picks_format_from_extension_or_contents() {
+        assert_eq!(
+            Some(InputFormat::Yaml),
+            InputFormat::from_path("config/app.yml")
+        );
+        assert_eq!(
+            Some(InputFormat::PhpFpm),
+            InputFormat::from_path("pool.d/env.conf")
+        );
+        assert_eq!(None, InputFormat::from_path(".env.local"));
+
+        assert_eq!(InputFormat::Json, InputFormat::detect("\n  {\"KEY\": 1}"));
+        assert_eq!(
+            InputFormat::PhpFpm,
+            InputFormat::detect("; pool\n[www]\nenv[KEY]=1")
+        );
+        assert_eq!(InputFormat::DotEnv, InputFormat::detect("# env\nKEY=1"));
+    }
+
+    #[test]
+    fn test_errors_leave_out_values() {
+        let inputs = [
+            (InputFormat::DotEnv, "A=\"hunter\"xyzzy\nB=\"hunter"),
+            (
+                InputFormat::PhpFpm,
+                "env[A]=\"hunter\"xyzzy\nenv[B]=\"hunter",
+            ),
+            (
+                InputFormat::Yaml,
+                "A: \"hunter\"xyzzy\nB: \"\\xhunter\"\nC: \"hunter",
+            ),
+            (InputFormat::Yaml, "hunter2"),
+            (InputFormat::Yaml, "- hunter2"),
+            (InputFormat::Json, "{\"A\": \"hunter"),
+            (InputFormat::Json, "\"hunter2\""),
+            (InputFormat::Json, "[\"hunter2\"]"),
+            (InputFormat::Json, "{\"A\": hunter2}"),
+            (InputFormat::Properties, "A=\\uhunter"),
+        ];
+
+        for (format, input) in inputs.iter() {
+            let parsed = format.parse(input);
+
+            assert!(!parsed.errors.is_empty(), "{:?}", format);
+
+            for error in parsed.errors {
+                let message = error.to_string();
+                assert!(!message.contains("hunter"), "{:?}: {}", format, message);
+                assert!(!message.contains("xyzzy"), "{:?}: {}", format, message);
+            }
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use super::{ParseErrorKind, Parsed};
+
+/// Parses the `env[KEY] = value` lines of a php-fpm pool file, as written by `PhpFpm`. Other
+/// pool directives, section headers and `;` comments are ignored, so whole pool files can be
+/// read. Double quoted values may span several lines and, as in PHP's INI parser, only `\"`,
+/// `\\` and `\$` are escapes. `${VAR}` is kept as is rather than interpolated.
+pub fn parse_php_fpm(input: &str) -> Parsed {
+    // Carriage returns are only stripped outside of quotes, as PHP keeps them in quoted values
+    let lines = input.split('\n').collect::<Vec<_>>();
+    let mut parsed = Parsed::default();
+    let mut index = 0;
+
+    while index < lines.len() {
+        let line = index + 1;
+
+        if let Some(entry) = lines[index].trim_start().strip_prefix("env[") {
+            match parse_entry(&lines, &mut index, entry) {
+                Ok((key, value)) => parsed.push(line, key, value),
+                Err(kind) => parsed.error(line, kind),
+            }
+        }
+
+        index += 1;
+    }
+
+    parsed
+}
+
+/// Parses what follows `env[`, leaving `index` on the last line it used
+fn parse_entry<'a>(
+    lines: &[&'a str],
+    index: &mut usize,
+    entry: &'a str,
+) -> Result<(String, String), ParseErrorKind> {
+    let (key, rest) = entry
+        .split_once(']')
+        .ok_or_else(|| ParseErrorKind::Syntax("expected env[KEY] = value".to_string()))?;
+    let value = rest
+        .trim_start()
+        .strip_prefix('=')
+        .ok_or(ParseErrorKind::MissingEquals)?
+        .trim_start();
+
+    let value = match value.strip_prefix('"') {
+        Some(quoted) => {
+            let (value, after) = parse_quoted(lines, index, quoted)?;
+            let after = after.trim();
+
+            if !after.is_empty() && !after.starts_with(';') {
+                return Err(ParseErrorKind::TrailingCharacters(after.chars().count()));
+            }
+
+            value
+        }
+        None => match value.find(';') {
+            Some(comment) => value[..comment].trim_end().to_string(),
+            None => value.trim_end().to_string(),
+        },
+    };
+
+    Ok((key.trim().to_string(), value))
+}
+
+/// Reads a double quoted INI value, continuing onto following lines until the closing quote.
+/// Returns the value and whatever follows the closing quote.
+fn parse_quoted<'a>(
+    lines: &[&'a str],
+    index: &mut usize,
+    first: &'a str,
+) -> Result<(String, &'a str), ParseErrorKind> {
+    let mut value = String::new();
+    let mut current = first;
+
+    loop {
+        let mut chars = current.char_indices().peekable();
+
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Ok((value, &current[i + 1..])),
+                '\\' => match chars.peek() {
+                    Some(&(_, escaped @ ('"' | '\\' | '$'))) => {
+                        value.push(escaped);
+                        chars.next();
+                    }
+                    _ => value.push('\\'),
+                },
+                c => value.push(c),
+            }
+        }
+
+        *index += 1;
+
+        // The rest of the file is part of the value, so it is not parsed any further
+        if *index >= lines.len() {
+            *index = lines.len() - 1;
+            return Err(ParseErrorKind::Unterminated);
+        }
+
+        value.push('\n');
+        current = lines[*index];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tests::pairs;
+    use crate::parser::ParseError;
+
+    #[test]
+    fn test_parses_pool_files() {
+        let parsed = parse_php_fpm(
+            "; legacy pool\n[www]\nuser = www-data\npm = dynamic\nenv[DB_HOST] = \"db.internal\"\nenv[PORT]=5432 ; comment\nenv[QUOTED]=\"a \\\"b\\\" ; c\"\n",
+        );
+
+        assert_eq!(Vec::<ParseError>::new(), parsed.errors);
+        assert_eq!(
+            vec![
+                ("DB_HOST", "db.internal"),
+                ("PORT", "5432"),
+                ("QUOTED", "a \"b\" ; c")
+            ],
+            pairs(&parsed)
+        );
+    }
+
+    #[test]
+    fn test_unescapes_like_php() {
+        // php-fpm reads these as C:\new\rtmp, a "b", a\b, $HOME ${HOME} and a value ending
+        // in a carriage return
+        let parsed = parse_php_fpm(
+            "env[WIN]=\"C:\\new\\rtmp\"\r\nenv[QUOTE]=\"a \\\"b\\\"\"\nenv[SLASH]=\"a\\\\b\"\nenv[DOLLAR]=\"\\$HOME \\${HOME}\"\nenv[CR]=\"x\r\"\n",
+        );
+
+        assert_eq!(Vec::<ParseError>::new(), parsed.errors);
+        assert_eq!(
+            vec![
+                ("WIN", "C:\\new\\rtmp"),
+                ("QUOTE", "a \"b\""),
+                ("SLASH", "a\\b"),
+                ("DOLLAR", "$HOME ${HOME}"),
+                ("CR", "x\r"),
+            ],
+            pairs(&parsed)
+        );
+    }
+
+    #[test]
+    fn test_reports_malformed_entries() {
+        let parsed = parse_php_fpm("env[A\nenv[B] 1\nenv[C]=\"x\" y\nenv[D]=\"open\n");
+
+        assert_eq!(
+            vec![1, 2, 3, 4],
+            parsed.errors.iter().map(|e| e.line).collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use super::{lines, ParseErrorKind, Parsed};
+
+/// Parses a Java `.properties` file. Keys and values are separated by `=`, `:` or whitespace,
+/// `#` and `!` start comments, a trailing `\` continues a value onto the next line and the
+/// usual `\t`, `\n` and `\uXXXX` escapes are supported.
+pub fn parse_properties(input: &str) -> Parsed {
+    let lines = lines(input);
+    let mut parsed = Parsed::default();
+    let mut index = 0;
+
+    while index < lines.len() {
+        let line = index + 1;
+        let logical = logical_line(&lines, &mut index);
+        index += 1;
+
+        let logical = logical.trim_start();
+
+        if logical.is_empty() || logical.starts_with('#') || logical.starts_with('!') {
+            continue;
+        }
+
+        match parse_entry(logical) {
+            Ok((key, value)) => parsed.push(line, key, value),
+            Err(kind) => parsed.error(line, kind),
+        }
+    }
+
+    parsed
+}
+
+/// Joins lines ending in an odd number of backslashes with the lines that follow, leaving
+/// `index` on the last line used
+fn logical_line(lines: &[&str], index: &mut usize) -> String {
+    let mut logical = String::new();
+    let mut current = lines[*index];
+
+    loop {
+        let trailing = current.len() - current.trim_end_matches('\\').len();
+
+        if trailing.is_multiple_of(2) || *index + 1 == lines.len() {
+            logical.push_str(current);
+            return logical;
+        }
+
+        logical.push_str(&current[..current.len() - 1]);
+        *index += 1;
+        current = lines[*index].trim_start();
+    }
+}
+
+/// Splits a logical line at the first unescaped separator and unescapes both halves
+fn parse_entry(line: &str) -> Result<(String, String), ParseErrorKind> {
+    let mut chars = line.char_indices();
+    let mut end = line.len();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '=' | ':' | ' ' | '\t' | '\x0c' => {
+                end = i;
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    let key = &line[..end];
+    let mut rest = line[end..].trim_start_matches(&[' ', '\t', '\x0c'][..]);
+
+    if let Some(after) = rest.strip_prefix(&['=', ':'][..]) {
+        rest = after.trim_start_matches(&[' ', '\t', '\x0c'][..]);
+    }
+
+    Ok((unescape(key)?, unescape(rest)?))
+}
+
+fn unescape(value: &str) -> Result<String, ParseErrorKind> {
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('f') => unescaped.push('\x0c'),
+            Some('u') => {
+                let hex = chars.by_ref().take(4).collect::<String>();
+                let c = u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .filter(|_| hex.len() == 4)
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| ParseErrorKind::Syntax("invalid \\u escape".to_string()))?;
+
+                unescaped.push(c);
+            }
+            Some(other) => unescaped.push(other),
+            None => {}
+        }
+    }
+
+    Ok(unescaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tests::pairs;
+    use crate::parser::ParseError;
+
+    #[test]
+    fn test_parses_properties() {
+        let parsed = parse_properties(
+            "# comment\n! also a comment\ndb.host = db.internal\ndb.port:5432\ngreeting Hello \\\n    world\npath=C:\\\\app\\tbin\nsnowman=\\u2603\nempty\n",
+        );
+
+        assert_eq!(Vec::<ParseError>::new(), parsed.errors);
+        assert_eq!(
+            vec![
+                ("db.host", "db.internal"),
+                ("db.port", "5432"),
+                ("greeting", "Hello world"),
+                ("path", "C:\\app\tbin"),
+                ("snowman", "☃"),
+                ("empty", "")
+            ],
+            pairs(&parsed)
+        );
+    }
+
+    #[test]
+    fn test_reports_lines_of_continued_values() {
+        let parsed = parse_properties("a=1 \\\n  2\nb=\\u12\n1bad=1");
+
+        assert_eq!(
+            vec![3, 4],
+            parsed.errors.iter().map(|e| e.line).collect::<Vec<_>>()
+        );
+    }
+}
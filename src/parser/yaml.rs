@@ -0,0 +1,269 @@
+use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
+use serde_yaml_ng::Value;
+
+use std::fmt;
+
+use super::{ParseErrorKind, Parsed};
+
+/// The entries of a YAML map in file order, including repeated keys which `Mapping` would
+/// reject
+struct Entries(Vec<(Value, Value)>);
+
+impl<'de> Deserialize<'de> for Entries {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(EntriesVisitor)
+    }
+}
+
+struct EntriesVisitor;
+
+impl<'de> Visitor<'de> for EntriesVisitor {
+    type Value = Entries;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a map of keys and values")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Entries, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut entries = vec![];
+
+        while let Some(entry) = map.next_entry::<Value, Value>()? {
+            entries.push(entry);
+        }
+
+        Ok(Entries(entries))
+    }
+}
+
+/// Parses a flat YAML map. Strings are used as is, numbers and booleans as written and `~` or
+/// `null` as an empty value. Nested maps, lists and tagged values are reported, as are numbers
+/// and booleans whose text cannot be found, e.g. in flow maps, as they would otherwise be
+/// normalized.
+pub fn parse_yaml(input: &str) -> Parsed {
+    let mut parsed = Parsed::default();
+
+    // An empty document, or one with only comments, has no entries rather than being an error
+    let entries = match serde_yaml_ng::from_str::<Option<Entries>>(input) {
+        Ok(entries) => entries.map_or_else(Vec::new, |Entries(entries)| entries),
+        Err(err) => {
+            let line = err.location().map_or(1, |location| location.line().max(1));
+            parsed.error(line, syntax_error(&err));
+            return parsed;
+        }
+    };
+
+    let mut cursor = 0;
+
+    for (key, value) in entries {
+        let key = match scalar(key) {
+            Some(key) => key,
+            None => {
+                let line = cursor + 1;
+                parsed.error(
+                    line,
+                    ParseErrorKind::Syntax("keys must be plain scalars".to_string()),
+                );
+                continue;
+            }
+        };
+        let line = line_of_key(input, &key, &mut cursor);
+
+        match value {
+            Value::Bool(_) | Value::Number(_) => match as_written(input, line, &key, &value) {
+                Some(value) => parsed.push(line, key, value),
+                None => parsed.error(line, ParseErrorKind::UnquotedNumber(key)),
+            },
+            value => match scalar(value) {
+                Some(value) => parsed.push(line, key, value),
+                None => parsed.error(line, ParseErrorKind::UnsupportedValue(key)),
+            },
+        }
+    }
+
+    parsed
+}
+
+/// The text of a scalar, or `None` for maps, lists and tagged values
+fn scalar(value: Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value),
+        Value::Null => Some(String::new()),
+        Value::Bool(value) => Some(value.to_string()),
+        Value::Number(value) => Some(value.to_string()),
+        Value::Sequence(_) | Value::Mapping(_) | Value::Tagged(_) => None,
+    }
+}
+
+/// The text after the colon when a line starts with a key, possibly quoted
+fn after_key<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let is_quote = |c| c == '"' || c == '\'';
+
+    line.trim_start_matches(is_quote)
+        .strip_prefix(key)?
+        .trim_start_matches(is_quote)
+        .trim_start()
+        .strip_prefix(':')
+}
+
+/// The text of a number or boolean on its key's line, as `serde_yaml_ng` normalizes them, e.g.
+/// `1.10` to `1.1`, `0x1F` to `31` and `True` to `true`. The text is only used when it reads
+/// back as the same value.
+fn as_written(input: &str, line: usize, key: &str, value: &Value) -> Option<String> {
+    let text = after_key(input.lines().nth(line.checked_sub(1)?)?, key)?;
+    let text = text.split('#').next()?.trim();
+
+    match serde_yaml_ng::from_str::<Value>(text) {
+        Ok(ref read) if read == value => Some(text.to_string()),
+        _ => None,
+    }
+}
+
+/// Describes why a file is not a YAML map by its column only, as the messages of `serde_yaml_ng`
+/// quote the values they trip over. They do not say whether the YAML itself is invalid, so the
+/// two cases share a message.
+fn syntax_error(err: &serde_yaml_ng::Error) -> ParseErrorKind {
+    let kind = "invalid YAML or not a map of keys and values";
+
+    ParseErrorKind::Syntax(match err.location() {
+        Some(location) => format!("{} at column {}", kind, location.column()),
+        None => kind.to_string(),
+    })
+}
+
+/// Finds the line a key was written on by searching forward from the previous key, as
+/// `serde_yaml_ng` does not keep positions. Keys of a flat map start their line, possibly quoted.
+fn line_of_key(input: &str, key: &str, cursor: &mut usize) -> usize {
+    let found = input
+        .lines()
+        .skip(*cursor)
+        .position(|line| after_key(line, key).is_some());
+
+    if let Some(offset) = found {
+        *cursor += offset + 1;
+    }
+
+    (*cursor).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tests::pairs;
+    use crate::parser::ParseError;
+
+    #[test]
+    fn test_parses_flat_maps() {
+        let parsed = parse_yaml(
+            "---\n# settings\nHOST: db.internal # primary\nPORT: 5432\n\"QUOTED KEY\": 'it''s'\nESCAPED: \"a\\tb \\u2603\"\nEMPTY:\nNONE: ~\nURL: http://example.com/#top\n",
+        );
+
+        assert_eq!(
+            vec![
+                ("HOST", "db.internal"),
+                ("PORT", "5432"),
+                ("ESCAPED", "a\tb ☃"),
+                ("EMPTY", ""),
+                ("NONE", ""),
+                ("URL", "http://example.com/#top")
+            ],
+            pairs(&parsed)
+        );
+        assert_eq!(
+            vec![ParseError {
+                line: 5,
+                kind: ParseErrorKind::InvalidKey("QUOTED KEY".to_string())
+            }],
+            parsed.errors
+        );
+    }
+
+    #[test]
+    fn test_keeps_numbers_and_booleans_as_written() {
+        let parsed = parse_yaml(
+            "A: 1.10\nB: 1e3\nC: 0x1F\nD: +12 # signed\nE: 0o17\nF: True\nG: .5\nH: 007\n",
+        );
+
+        assert_eq!(Vec::<ParseError>::new(), parsed.errors);
+        assert_eq!(
+            vec![
+                ("A", "1.10"),
+                ("B", "1e3"),
+                ("C", "0x1F"),
+                ("D", "+12"),
+                ("E", "0o17"),
+                ("F", "True"),
+                ("G", ".5"),
+                ("H", "007")
+            ],
+            pairs(&parsed)
+        );
+
+        let parsed = parse_yaml("{A: 1.10, B: x}\n");
+        assert_eq!(vec![("B", "x")], pairs(&parsed));
+        assert_eq!(
+            vec![ParseError {
+                line: 1,
+                kind: ParseErrorKind::UnquotedNumber("A".to_string())
+            }],
+            parsed.errors
+        );
+    }
+
+    #[test]
+    fn test_parses_block_scalars() {
+        let parsed = parse_yaml(
+            "CERT: |\n  -----BEGIN-----\n  abc\n\n  -----END-----\n\nFOLDED: >-\n  one\n  two\n\n  three\nNEXT: 1\n",
+        );
+
+        assert_eq!(Vec::<ParseError>::new(), parsed.errors);
+        assert_eq!(
+            vec![
+                ("CERT", "-----BEGIN-----\nabc\n\n-----END-----\n"),
+                ("FOLDED", "one two\nthree"),
+                ("NEXT", "1")
+            ],
+            pairs(&parsed)
+        );
+    }
+
+    #[test]
+    fn test_reports_nested_values() {
+        let parsed =
+            parse_yaml("DB:\n  host: db\n  port: 5432\nLIST:\n- a\n- b\nFLOW: [a, b]\nOK: yes\n");
+
+        assert_eq!(vec![("OK", "yes")], pairs(&parsed));
+        assert_eq!(
+            vec![1, 4, 7],
+            parsed.errors.iter().map(|e| e.line).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_reports_repeated_keys_and_invalid_yaml() {
+        let parsed = parse_yaml("A: 1\nB: 2\nA: 3\n");
+
+        assert_eq!(vec![("B", "2"), ("A", "3")], pairs(&parsed));
+        assert_eq!(
+            vec![ParseError {
+                line: 3,
+                kind: ParseErrorKind::DuplicateKey {
+                    key: "A".to_string(),
+                    first_line: 1
+                }
+            }],
+            parsed.errors
+        );
+
+        let parsed = parse_yaml("A: 1\nB: \"open\n");
+        assert_eq!(1, parsed.errors.len());
+        assert!(matches!(parsed.errors[0].kind, ParseErrorKind::Syntax(_)));
+
+        assert_eq!(Parsed::default(), parse_yaml("# nothing here\n"));
+    }
+}
@@ -0,0 +1,374 @@
+use async_trait::async_trait;
+use tracing::debug;
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+
+use crate::params::ParamBag;
+
+/// Maximum number of names accepted by a single `GetParameters` request
+const BATCH_SIZE: usize = 10;
+
+/// Number of times a reference may resolve to another reference before giving up
+const MAX_DEPTH: usize = 5;
+
+/// Secrets Manager secrets can be read through Parameter Store by prefixing the secret id
+const SECRETS_MANAGER_PREFIX: &str = "/aws/reference/secretsmanager/";
+
+#[async_trait]
+pub trait ResolveClient {
+    /// Fetches the decrypted values of the given names. Names that do not exist are left out of
+    /// the returned map.
+    async fn get_values(&self, names: &[String])
+        -> Result<HashMap<String, String>, Box<dyn Error>>;
+}
+
+#[async_trait]
+impl ResolveClient for aws_sdk_ssm::Client {
+    async fn get_values(
+        &self,
+        names: &[String],
+    ) -> Result<HashMap<String, String>, Box<dyn Error>> {
+        let resp = self
+            .get_parameters()
+            .set_names(Some(names.to_vec()))
+            .with_decryption(true)
+            .send()
+            .await
+            .map_err(Box::new)?;
+
+        Ok(resp
+            .parameters
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|parameter| match (parameter.name, parameter.value) {
+                (Some(name), Some(value)) => Some((name, value)),
+                _ => None,
+            })
+            .collect())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Reference {
+    Ssm(String),
+    SecretsManager { id: String, field: Option<String> },
+}
+
+impl Reference {
+    /// Parses `ssm:<name>` and `secretsmanager:<secret id>[#<json field>]` values
+    pub fn parse(value: &str) -> Option<Reference> {
+        if let Some(name) = value.strip_prefix("ssm:") {
+            Some(Reference::Ssm(name.to_string()))
+        } else if let Some(id) = value.strip_prefix("secretsmanager:") {
+            match id.split_once('#') {
+                Some((id, field)) => Some(Reference::SecretsManager {
+                    id: id.to_string(),
+                    field: Some(field.to_string()),
+                }),
+                None => Some(Reference::SecretsManager {
+                    id: id.to_string(),
+                    field: None,
+                }),
+            }
+        } else {
+            None
+        }
+    }
+
+    /// The Parameter Store name to request for this reference
+    fn name(&self) -> String {
+        match self {
+            Reference::Ssm(name) => name.clone(),
+            Reference::SecretsManager { id, .. } => SECRETS_MANAGER_PREFIX.to_string() + id,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ReferenceError {
+    NotFound { key: String, name: String },
+    MissingField { key: String, field: String },
+    TooDeep(String),
+    Client(Box<dyn Error>),
+}
+
+impl fmt::Display for ReferenceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReferenceError::NotFound { key, name } => {
+                write!(f, "{} references {} which does not exist", key, name)
+            }
+            ReferenceError::MissingField { key, field } => write!(
+                f,
+                "{} references field {} which is not in the secret",
+                key, field
+            ),
+            ReferenceError::TooDeep(key) => write!(
+                f,
+                "{} did not resolve after following {} references",
+                key, MAX_DEPTH
+            ),
+            ReferenceError::Client(err) => write!(f, "Failed to resolve references: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ReferenceError {}
+
+/// Resolves parameter values that point at other Parameter Store parameters or Secrets Manager
+/// secrets. Lookups are batched and cached, so each referenced name is only fetched once.
+pub struct ReferenceResolver<'a, T> {
+    client: &'a T,
+    cache: HashMap<String, String>,
+}
+
+impl<'a, T> ReferenceResolver<'a, T>
+where
+    T: ResolveClient,
+{
+    pub fn new(client: &'a T) -> Self {
+        ReferenceResolver {
+            client,
+            cache: HashMap::new(),
+        }
+    }
+
+    pub async fn resolve(&mut self, bag: &mut ParamBag) -> Result<(), ReferenceError> {
+        for _ in 0..MAX_DEPTH {
+            let references = bag
+                .params
+                .iter()
+                .enumerate()
+                .filter_map(|(i, param)| Reference::parse(param.value.expose()).map(|r| (i, r)))
+                .collect::<Vec<(usize, Reference)>>();
+
+            if references.is_empty() {
+                return Ok(());
+            }
+
+            self.fetch(references.iter().map(|(_, r)| r.name()).collect())
+                .await?;
+
+            for (i, reference) in references {
+                let param = &mut bag.params[i];
+                let name = reference.name();
+
+                let value = self
+                    .cache
+                    .get(&name)
+                    .ok_or_else(|| ReferenceError::NotFound {
+                        key: param.key.clone(),
+                        name,
+                    })?;
+
+                param.value = match reference {
+                    Reference::SecretsManager {
+                        field: Some(field), ..
+                    } => extract_field(value, &field)
+                        .ok_or(ReferenceError::MissingField {
+                            key: param.key.clone(),
+                            field,
+                        })?
+                        .into(),
+                    _ => value.clone().into(),
+                };
+            }
+        }
+
+        match bag
+            .params
+            .iter()
+            .find(|p| Reference::parse(p.value.expose()).is_some())
+        {
+            Some(param) => Err(ReferenceError::TooDeep(param.key.clone())),
+            None => Ok(()),
+        }
+    }
+
+    async fn fetch(&mut self, names: HashSet<String>) -> Result<(), ReferenceError> {
+        let missing = names
+            .into_iter()
+            .filter(|name| !self.cache.contains_key(name))
+            .collect::<Vec<String>>();
+
+        for chunk in missing.chunks(BATCH_SIZE) {
+            debug!(?chunk, "Fetching referenced parameters");
+
+            let values = self
+                .client
+                .get_values(chunk)
+                .await
+                .map_err(ReferenceError::Client)?;
+
+            self.cache.extend(values);
+        }
+
+        Ok(())
+    }
+}
+
+fn extract_field(secret: &str, field: &str) -> Option<String> {
+    match serde_json::from_str::<serde_json::Value>(secret)
+        .ok()?
+        .get(field)?
+    {
+        serde_json::Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::params::tests::bag;
+
+    struct MapClient {
+        values: HashMap<String, String>,
+        calls: Mutex<Vec<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl ResolveClient for MapClient {
+        async fn get_values(
+            &self,
+            names: &[String],
+        ) -> Result<HashMap<String, String>, Box<dyn Error>> {
+            self.calls.lock().unwrap().push(names.to_vec());
+
+            Ok(names
+                .iter()
+                .filter_map(|name| self.values.get(name).map(|v| (name.clone(), v.clone())))
+                .collect())
+        }
+    }
+
+    fn client(values: &[(&str, &str)]) -> MapClient {
+        MapClient {
+            values: values
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            calls: Mutex::new(vec![]),
+        }
+    }
+
+    #[test]
+    fn test_parses_references() {
+        assert_eq!(
+            Some(Reference::Ssm("/shared/db/password".to_string())),
+            Reference::parse("ssm:/shared/db/password")
+        );
+        assert_eq!(
+            Some(Reference::SecretsManager {
+                id: "prod/db".to_string(),
+                field: Some("password".to_string())
+            }),
+            Reference::parse("secretsmanager:prod/db#password")
+        );
+        assert_eq!(None, Reference::parse("plain value"));
+    }
+
+    #[tokio::test]
+    async fn test_resolves_ssm_and_secrets_manager_references() {
+        let client = client(&[
+            ("/shared/db/host", "db.example.com"),
+            (
+                "/aws/reference/secretsmanager/prod/db",
+                r#"{"password":"hunter2","port":5432}"#,
+            ),
+        ]);
+        let mut bag = bag(&[
+            ("HOST", "ssm:/shared/db/host"),
+            ("PASS", "secretsmanager:prod/db#password"),
+            ("PORT", "secretsmanager:prod/db#port"),
+            ("PLAIN", "value"),
+        ]);
+
+        ReferenceResolver::new(&client)
+            .resolve(&mut bag)
+            .await
+            .unwrap();
+
+        assert_eq!("db.example.com", bag.params[0].value);
+        assert_eq!("hunter2", bag.params[1].value);
+        assert_eq!("5432", bag.params[2].value);
+        assert_eq!("value", bag.params[3].value);
+        assert_eq!(1, client.calls.lock().unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn test_batches_and_caches_lookups() {
+        let names = (0..15)
+            .map(|i| (format!("/shared/{}", i), i.to_string()))
+            .collect::<Vec<(String, String)>>();
+        let client = client(
+            &names
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect::<Vec<_>>(),
+        );
+        let refs = names
+            .iter()
+            .map(|(k, _)| (k.clone(), format!("ssm:{}", k)))
+            .collect::<Vec<_>>();
+        let mut params = refs
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect::<Vec<_>>();
+        params.push(("DUPLICATE", "ssm:/shared/0"));
+
+        let mut bag = bag(&params);
+        let mut resolver = ReferenceResolver::new(&client);
+
+        resolver.resolve(&mut bag).await.unwrap();
+
+        let calls = client.calls.lock().unwrap();
+        assert_eq!(2, calls.len());
+        assert_eq!(15, calls.iter().map(|c| c.len()).sum::<usize>());
+        assert_eq!("0", bag.params[15].value);
+    }
+
+    #[tokio::test]
+    async fn test_follows_nested_references_up_to_a_limit() {
+        let nested = client(&[("/a", "ssm:/b"), ("/b", "value")]);
+        let mut bag = bag(&[("KEY", "ssm:/a")]);
+
+        ReferenceResolver::new(&nested)
+            .resolve(&mut bag)
+            .await
+            .unwrap();
+        assert_eq!("value", bag.params[0].value);
+
+        let looping = client(&[("/a", "ssm:/b"), ("/b", "ssm:/a")]);
+        let mut bag = self::bag(&[("KEY", "ssm:/a")]);
+
+        match ReferenceResolver::new(&looping).resolve(&mut bag).await {
+            Err(ReferenceError::TooDeep(key)) => assert_eq!("KEY", key),
+            other => panic!("Expected too deep error, found {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reports_missing_references() {
+        let client = client(&[("/aws/reference/secretsmanager/prod/db", "{}")]);
+
+        let mut bag = bag(&[("KEY", "ssm:/missing")]);
+        match ReferenceResolver::new(&client).resolve(&mut bag).await {
+            Err(ReferenceError::NotFound { key, name }) => {
+                assert_eq!("KEY", key);
+                assert_eq!("/missing", name);
+            }
+            other => panic!("Expected not found error, found {:?}", other),
+        }
+
+        let mut bag = self::bag(&[("KEY", "secretsmanager:prod/db#password")]);
+        match ReferenceResolver::new(&client).resolve(&mut bag).await {
+            Err(ReferenceError::MissingField { field, .. }) => assert_eq!("password", field),
+            other => panic!("Expected missing field error, found {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,370 @@
+use async_trait::async_trait;
+use aws_sdk_ssm::model::ParameterType;
+use aws_sdk_ssm::SdkError;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+use crate::output;
+use crate::params::{get_all_params, Param, ParamBag, ParamMeta};
+use crate::tags::Tag;
+use crate::transform::KeyTransform;
+
+/// Maximum number of names accepted by a single `DeleteParameters` request
+const BATCH_SIZE: usize = 10;
+
+#[async_trait]
+pub trait DeleteClient {
+    /// Deletes the given names, returning those that did not exist
+    async fn delete_names(&self, names: &[String]) -> Result<Vec<String>, Box<dyn Error>>;
+}
+
+#[async_trait]
+impl DeleteClient for aws_sdk_ssm::Client {
+    async fn delete_names(&self, names: &[String]) -> Result<Vec<String>, Box<dyn Error>> {
+        let resp = self
+            .delete_parameters()
+            .set_names(Some(names.to_vec()))
+            .send()
+            .await
+            .map_err(Box::new)?;
+
+        Ok(resp.invalid_parameters.unwrap_or_default())
+    }
+}
+
+/// The outcome of deleting parameters
+#[derive(Debug, Default, PartialEq)]
+pub struct RemoveSummary {
+    pub deleted: Vec<String>,
+    pub failed: Vec<(String, String)>,
+}
+
+impl RemoveSummary {
+    /// Fails when any parameter could not be deleted, so that `rm` exits with an error
+    pub fn check(&self) -> Result<(), String> {
+        if self.failed.is_empty() {
+            return Ok(());
+        }
+
+        Err(format!(
+            "Failed to delete {} of {} parameters",
+            self.failed.len(),
+            self.failed.len() + self.deleted.len()
+        ))
+    }
+}
+
+/// Finds what `rm` would delete: the parameter called `name`, or everything below it when
+/// recursive. Values are only decrypted when they are going to be backed up.
+pub async fn targets(
+    client: &aws_sdk_ssm::Client,
+    name: &str,
+    recursive: bool,
+    decrypt: bool,
+) -> Result<Vec<Param>, Box<dyn Error>> {
+    if recursive {
+        let bag = ParamBag::new(name)
+            .with_recursive(true)
+            .with_decryption(decrypt);
+
+        return Ok(get_all_params(client, bag)
+            .await
+            .map_err(|err| format!("Failed to get parameters: {}", err))?
+            .params);
+    }
+
+    let resp = client
+        .get_parameter()
+        .name(name)
+        .with_decryption(decrypt)
+        .send()
+        .await
+        .map_err(|err| match err {
+            SdkError::ServiceError { err, .. } if err.is_parameter_not_found() => format!(
+                "{} does not exist, pass --recursive to delete the parameters below it",
+                name
+            ),
+            err => format!("Failed to get {}: {}", name, err),
+        })?;
+
+    Ok(resp
+        .parameter
+        .and_then(|parameter| Param::from_parameter(parameter, &KeyTransform::default()))
+        .into_iter()
+        .collect())
+}
+
+/// Deletes parameters in batches. When a whole batch fails all of its names are reported.
+pub async fn remove<T>(client: &T, names: &[String]) -> RemoveSummary
+where
+    T: DeleteClient,
+{
+    let mut summary = RemoveSummary::default();
+
+    for batch in names.chunks(BATCH_SIZE) {
+        match client.delete_names(batch).await {
+            Ok(invalid) => {
+                for name in batch {
+                    if invalid.contains(name) {
+                        output::error(format!("Failed to delete {}, it does not exist", name));
+                        summary
+                            .failed
+                            .push((name.clone(), "does not exist".to_string()));
+                    } else {
+                        output::status(format!("Deleted {}", name));
+                        summary.deleted.push(name.clone());
+                    }
+                }
+            }
+            Err(err) => {
+                output::error(format!(
+                    "Failed to delete {} due to {}",
+                    batch.join(", "),
+                    err
+                ));
+
+                for name in batch {
+                    summary.failed.push((name.clone(), err.to_string()));
+                }
+            }
+        }
+    }
+
+    summary
+}
+
+/// The names, types, values and tags of parameters, saved by `export` and by `rm --backup`
+/// before deleting them
+pub fn snapshot(params: &[Param]) -> Value {
+    Value::Array(
+        params
+            .iter()
+            .filter_map(|param| {
+                let meta = param.meta.as_ref()?;
+
+                Some(json!({
+                    "name": meta.name,
+                    "type": meta.param_type.as_ref().map(|t| t.as_str()),
+                    "value": param.value.expose(),
+                    "tags": meta
+                        .tags
+                        .iter()
+                        .map(|tag| (tag.key.clone(), Value::from(tag.value.clone())))
+                        .collect::<Map<_, _>>(),
+                }))
+            })
+            .collect(),
+    )
+}
+
+/// A parameter as saved in a snapshot
+#[derive(Deserialize)]
+struct Saved {
+    name: String,
+    #[serde(rename = "type")]
+    param_type: Option<String>,
+    value: String,
+    /// Missing from backups saved before tags were kept
+    #[serde(default)]
+    tags: BTreeMap<String, String>,
+}
+
+/// Reads a snapshot saved by `export` or `rm --backup` back into parameters keyed by their names,
+/// for `restore` to write. Errors give the position only, as the file holds values.
+pub fn read_backup(contents: &str) -> Result<Vec<Param>, String> {
+    let saved = serde_json::from_str::<Vec<Saved>>(contents).map_err(|err| {
+        format!(
+            "not a backup saved by rm --backup (line {} column {})",
+            err.line(),
+            err.column()
+        )
+    })?;
+
+    Ok(saved
+        .into_iter()
+        .map(|saved| Param {
+            key: saved.name.clone(),
+            value: saved.value.into(),
+            meta: Some(ParamMeta {
+                name: saved.name,
+                param_type: saved.param_type.as_deref().map(ParameterType::from),
+                version: 0,
+                arn: None,
+                data_type: None,
+                last_modified: None,
+                tags: saved
+                    .tags
+                    .into_iter()
+                    .map(|(key, value)| Tag { key, value })
+                    .collect(),
+            }),
+        })
+        .collect())
+}
+
+/// Saves a snapshot of parameters to a new file only the current user can read. An existing
+/// file is never overwritten, as it may be an earlier backup.
+pub fn save_backup(path: impl AsRef<Path>, params: &[Param]) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    options
+        .open(path)?
+        .write_all(snapshot(params).to_string().as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use aws_sdk_ssm::model::ParameterType;
+
+    use super::*;
+    use crate::params::tests::{meta, param};
+    use crate::params::ParamMeta;
+
+    #[derive(Default)]
+    struct RecordingClient {
+        batches: Mutex<Vec<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl DeleteClient for RecordingClient {
+        async fn delete_names(&self, names: &[String]) -> Result<Vec<String>, Box<dyn Error>> {
+            self.batches.lock().unwrap().push(names.to_vec());
+
+            match names.len() {
+                10 => Ok(vec!["/app/3".to_string()]),
+                _ => Err("throttled".into()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deletes_in_batches_of_ten() {
+        let client = RecordingClient::default();
+        let names = (0..12).map(|i| format!("/app/{}", i)).collect::<Vec<_>>();
+
+        let summary = remove(&client, &names).await;
+
+        assert_eq!(
+            vec![10, 2],
+            client
+                .batches
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|b| b.len())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(9, summary.deleted.len());
+        assert_eq!(
+            vec![
+                ("/app/3".to_string(), "does not exist".to_string()),
+                ("/app/10".to_string(), "throttled".to_string()),
+                ("/app/11".to_string(), "throttled".to_string()),
+            ],
+            summary.failed
+        );
+        assert_eq!(
+            Err("Failed to delete 3 of 12 parameters".to_string()),
+            summary.check()
+        );
+        assert_eq!(Ok(()), RemoveSummary::default().check());
+    }
+
+    #[test]
+    fn test_snapshots_names_types_and_values() {
+        let params = vec![Param {
+            meta: Some(ParamMeta {
+                param_type: Some(ParameterType::SecureString),
+                ..meta("/app/db_password")
+            }),
+            ..param("DB_PASSWORD", "secret")
+        }];
+
+        assert_eq!(
+            json!([{"name": "/app/db_password", "type": "SecureString", "value": "secret", "tags": {}}]),
+            snapshot(&params)
+        );
+    }
+
+    #[test]
+    fn test_reads_back_snapshots() {
+        let params = vec![
+            Param {
+                meta: Some(ParamMeta {
+                    param_type: Some(ParameterType::SecureString),
+                    ..meta("/app/db_password")
+                }),
+                ..param("DB_PASSWORD", "hunter2")
+            },
+            Param {
+                meta: Some(ParamMeta {
+                    param_type: Some(ParameterType::StringList),
+                    tags: vec!["owner=platform".parse().unwrap()],
+                    ..meta("/app/sub/hosts")
+                }),
+                ..param("HOSTS", "a,b")
+            },
+        ];
+
+        let restored = read_backup(&snapshot(&params).to_string()).unwrap();
+        let saved = |params: &[Param]| {
+            params
+                .iter()
+                .map(|p| {
+                    let meta = p.meta.as_ref().unwrap();
+                    (
+                        meta.name.clone(),
+                        meta.param_type.clone(),
+                        p.value.clone(),
+                        meta.tags.clone(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(saved(&params), saved(&restored));
+        assert_eq!("/app/sub/hosts", restored[1].key);
+
+        let untagged = read_backup(r#"[{"name": "/app/a", "type": "String", "value": "x"}]"#);
+        assert!(untagged.unwrap()[0].meta.as_ref().unwrap().tags.is_empty());
+
+        let err = read_backup(r#"{"DB_PASSWORD": "hunter2"}"#).unwrap_err();
+        assert!(!err.contains("hunter2"), "{}", err);
+    }
+
+    #[test]
+    fn test_saves_backups_to_new_private_files() {
+        let path = std::env::temp_dir().join(format!("envfmt-backup-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        save_backup(&path, &[]).unwrap();
+        assert_eq!("[]", std::fs::read_to_string(&path).unwrap());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(0o600, mode & 0o777);
+        }
+
+        let err = save_backup(&path, &[]).unwrap_err();
+        assert_eq!(io::ErrorKind::AlreadyExists, err.kind());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,59 @@
+use std::fmt;
+
+/// What `--mask` prints in place of values
+pub const MASK: &str = "********";
+
+/// A value that may be sensitive. It is redacted in `Debug` output, which is what tracing
+/// records, and has no `Display`, so writing it anywhere takes a call to `expose`.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Secret(value)
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(value: &str) -> Self {
+        Secret(value.to_string())
+    }
+}
+
+impl PartialEq<&str> for Secret {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<Secret> for &str {
+    fn eq(&self, other: &Secret) -> bool {
+        *self == other.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Secret(..)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redacts_debug_output() {
+        let secret = Secret::from("hunter2");
+
+        assert_eq!("Secret(..)", format!("{:?}", secret));
+        assert_eq!("Some(Secret(..))", format!("{:?}", Some(&secret)));
+        assert_eq!("hunter2", secret.expose());
+    }
+}
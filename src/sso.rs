@@ -0,0 +1,303 @@
+use aws_config::profile::{Profile, ProfileSet};
+use aws_smithy_types::date_time::Format;
+use aws_smithy_types::DateTime;
+use aws_types::credentials::{self, future, Credentials, CredentialsError, ProvideCredentials};
+use hyper::{Body, Request};
+use serde_json::Value;
+use sha1::{Digest, Sha1};
+use tracing::debug;
+
+use std::convert::TryFrom;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Credentials for a profile configured for AWS SSO. The access token is read from the cache
+/// written by `aws sso login`, so envfmt never starts a login itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SsoCredentialsProvider {
+    profile: String,
+    start_url: String,
+    region: String,
+    account_id: String,
+    role_name: String,
+    cache_dir: PathBuf,
+}
+
+impl SsoCredentialsProvider {
+    /// Reads the `sso_*` settings of a profile. Returns `None` if the profile does not use SSO.
+    pub fn from_profile(profile: &Profile) -> Result<Option<Self>, String> {
+        let start_url = match profile.get("sso_start_url") {
+            Some(url) => url,
+            None => return Ok(None),
+        };
+
+        let setting = |key: &str| {
+            profile.get(key).map(|v| v.to_string()).ok_or_else(|| {
+                format!(
+                    "Profile {} uses SSO but does not set {}",
+                    profile.name(),
+                    key
+                )
+            })
+        };
+
+        let cache_dir = std::env::var_os("HOME")
+            .or_else(|| std::env::var_os("USERPROFILE"))
+            .map(|home| PathBuf::from(home).join(".aws/sso/cache"))
+            .unwrap_or_default();
+
+        Ok(Some(SsoCredentialsProvider {
+            profile: profile.name().to_string(),
+            start_url: start_url.to_string(),
+            region: setting("sso_region")?,
+            account_id: setting("sso_account_id")?,
+            role_name: setting("sso_role_name")?,
+            cache_dir,
+        }))
+    }
+
+    /// Looks up a profile in the shared config files and reads its SSO settings
+    pub async fn for_profile(name: &str) -> Result<Option<Self>, String> {
+        let profiles: ProfileSet = aws_config::profile::load(
+            &aws_types::os_shim_internal::Fs::default(),
+            &aws_types::os_shim_internal::Env::default(),
+        )
+        .await
+        .map_err(|err| err.to_string())?;
+
+        match profiles.get_profile(name) {
+            Some(profile) => Self::from_profile(profile),
+            None => Ok(None),
+        }
+    }
+
+    /// The AWS CLI names cached tokens after the SHA-1 of the start URL
+    fn token_path(&self) -> PathBuf {
+        let hash = Sha1::digest(self.start_url.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+
+        self.cache_dir.join(hash + ".json")
+    }
+
+    fn cached_token(&self) -> Result<String, CredentialsError> {
+        let expired = || {
+            CredentialsError::not_loaded(format!(
+                "The SSO session for profile {} has expired or is missing, run `aws sso login --profile {}`",
+                self.profile, self.profile
+            ))
+        };
+
+        let contents = fs::read_to_string(self.token_path()).map_err(|_| expired())?;
+        let entry =
+            serde_json::from_str::<Value>(&contents).map_err(CredentialsError::unhandled)?;
+
+        // Older versions of the AWS CLI write a `UTC` suffix instead of `Z`
+        let expires_at = entry
+            .get("expiresAt")
+            .and_then(|e| e.as_str())
+            .map(|e| e.replace("UTC", "Z"))
+            .ok_or_else(expired)?;
+        let expires_at = DateTime::from_str(&expires_at, Format::DateTime)
+            .ok()
+            .and_then(|t| SystemTime::try_from(t).ok())
+            .ok_or_else(expired)?;
+
+        if expires_at < SystemTime::now() {
+            return Err(expired());
+        }
+
+        entry
+            .get("accessToken")
+            .and_then(|t| t.as_str())
+            .map(|t| t.to_string())
+            .ok_or_else(expired)
+    }
+
+    async fn credentials(&self) -> credentials::Result {
+        let token = self.cached_token()?;
+
+        let uri = format!(
+            "https://portal.sso.{}.amazonaws.com/federation/credentials?account_id={}&role_name={}",
+            self.region,
+            encode(&self.account_id),
+            encode(&self.role_name)
+        );
+        debug!(%uri, "Requesting SSO role credentials");
+
+        let request = Request::get(uri)
+            .header("x-amz-sso_bearer_token", token)
+            .body(Body::empty())
+            .map_err(CredentialsError::unhandled)?;
+
+        let client =
+            hyper::Client::builder().build(hyper_rustls::HttpsConnector::with_native_roots());
+        let response = client
+            .request(request)
+            .await
+            .map_err(CredentialsError::provider_error)?;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body())
+            .await
+            .map_err(CredentialsError::provider_error)?;
+
+        if !status.is_success() {
+            return Err(CredentialsError::provider_error(format!(
+                "SSO returned {}: {}",
+                status,
+                String::from_utf8_lossy(&body)
+            )));
+        }
+
+        parse_role_credentials(&body)
+    }
+}
+
+impl ProvideCredentials for SsoCredentialsProvider {
+    fn provide_credentials<'a>(&'a self) -> future::ProvideCredentials<'a>
+    where
+        Self: 'a,
+    {
+        future::ProvideCredentials::new(self.credentials())
+    }
+}
+
+/// Percent encodes a query string value
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Parses a `GetRoleCredentials` response, whose expiration is in milliseconds since the epoch
+fn parse_role_credentials(body: &[u8]) -> credentials::Result {
+    let response = serde_json::from_slice::<Value>(body).map_err(CredentialsError::unhandled)?;
+    let creds = response
+        .get("roleCredentials")
+        .ok_or_else(|| CredentialsError::unhandled("SSO response is missing roleCredentials"))?;
+    let field = |key: &str| {
+        creds
+            .get(key)
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| CredentialsError::unhandled(format!("SSO response is missing {}", key)))
+    };
+
+    Ok(Credentials::new(
+        field("accessKeyId")?,
+        field("secretAccessKey")?,
+        field("sessionToken").ok().map(|t| t.to_string()),
+        creds
+            .get("expiration")
+            .and_then(|e| e.as_u64())
+            .map(|ms| UNIX_EPOCH + Duration::from_millis(ms)),
+        "Sso",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_types::os_shim_internal::{Env, Fs};
+
+    use super::*;
+
+    async fn provider(config: &str) -> Result<Option<SsoCredentialsProvider>, String> {
+        let set = aws_config::profile::load(
+            &Fs::from_slice(&[("config", config)]),
+            &Env::from_slice(&[("AWS_CONFIG_FILE", "config")]),
+        )
+        .await
+        .unwrap();
+
+        SsoCredentialsProvider::from_profile(set.get_profile("dev").unwrap())
+    }
+
+    fn cached(sso: &SsoCredentialsProvider, name: &str, token: &str) -> SsoCredentialsProvider {
+        let dir = std::env::temp_dir().join(format!("envfmt-sso-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let sso = SsoCredentialsProvider {
+            cache_dir: dir,
+            ..sso.clone()
+        };
+        fs::write(sso.token_path(), token).unwrap();
+
+        sso
+    }
+
+    #[tokio::test]
+    async fn test_reads_sso_profiles() {
+        let sso = provider("[profile dev]\nsso_start_url = https://example.awsapps.com/start\nsso_region = eu-west-1\nsso_account_id = 123456789012\nsso_role_name = Developer\n")
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!("Developer", sso.role_name);
+        assert_eq!(
+            None,
+            provider("[profile dev]\nregion = eu-west-1\n")
+                .await
+                .unwrap()
+        );
+        assert!(
+            provider("[profile dev]\nsso_start_url = https://example.awsapps.com/start\n")
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reads_cached_tokens() {
+        let sso = provider("[profile dev]\nsso_start_url = https://example.awsapps.com/start\nsso_region = eu-west-1\nsso_account_id = 123456789012\nsso_role_name = Developer\n")
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(sso
+            .token_path()
+            .ends_with("e8be5486177c5b5392bd9aa76563515b29358e6e.json"));
+
+        let valid = cached(
+            &sso,
+            "valid",
+            r#"{"accessToken":"token","expiresAt":"2999-01-01T00:00:00UTC"}"#,
+        );
+        assert_eq!("token", valid.cached_token().unwrap());
+
+        let expired = cached(
+            &sso,
+            "expired",
+            r#"{"accessToken":"token","expiresAt":"2000-01-01T00:00:00Z"}"#,
+        );
+        assert!(expired.cached_token().is_err());
+    }
+
+    #[test]
+    fn test_parses_role_credentials() {
+        let creds = parse_role_credentials(
+            br#"{"roleCredentials":{"accessKeyId":"key","secretAccessKey":"secret","sessionToken":"token","expiration":1600000000000}}"#,
+        )
+        .unwrap();
+
+        assert_eq!("key", creds.access_key_id());
+        assert_eq!(Some("token"), creds.session_token());
+        assert_eq!(
+            Some(UNIX_EPOCH + Duration::from_secs(1_600_000_000)),
+            creds.expiry()
+        );
+        assert!(parse_role_credentials(b"{}").is_err());
+    }
+
+    #[test]
+    fn test_encodes_query_values() {
+        assert_eq!("Admin%2BDev%40x", encode("Admin+Dev@x"));
+    }
+}
@@ -0,0 +1,280 @@
+use async_trait::async_trait;
+use aws_sdk_ssm::model::{ParameterStringFilter, ResourceTypeForTagging};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use tracing::debug;
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::params::{Param, ParamBag};
+
+/// Number of parameters whose tags are fetched at once by `fetch_tags`
+const CONCURRENCY: usize = 4;
+
+/// A tag given as `key=value`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tag {
+    pub key: String,
+    pub value: String,
+}
+
+impl FromStr for Tag {
+    type Err = TagError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((key, value)) if !key.is_empty() => Ok(Tag {
+                key: key.to_string(),
+                value: value.to_string(),
+            }),
+            _ => Err(TagError(s.to_string())),
+        }
+    }
+}
+
+impl From<&Tag> for aws_sdk_ssm::model::Tag {
+    fn from(tag: &Tag) -> Self {
+        aws_sdk_ssm::model::Tag::builder()
+            .key(&tag.key)
+            .value(&tag.value)
+            .build()
+    }
+}
+
+impl From<aws_sdk_ssm::model::Tag> for Tag {
+    fn from(tag: aws_sdk_ssm::model::Tag) -> Self {
+        Tag {
+            key: tag.key.unwrap_or_default(),
+            value: tag.value.unwrap_or_default(),
+        }
+    }
+}
+
+/// Selects parameters carrying the tag with `DescribeParameters`, as `GetParametersByPath`
+/// does not accept tag filters
+impl From<&Tag> for ParameterStringFilter {
+    fn from(tag: &Tag) -> Self {
+        ParameterStringFilter::builder()
+            .key(format!("tag:{}", tag.key))
+            .option("Equals")
+            .values(&tag.value)
+            .build()
+    }
+}
+
+#[derive(Debug)]
+pub struct TagError(String);
+
+impl fmt::Display for TagError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} is not a tag like service=api", self.0)
+    }
+}
+
+impl Error for TagError {}
+
+#[async_trait]
+pub trait TagClient {
+    /// Finds the names of the parameters below a path carrying all of the tags
+    async fn tagged(
+        &self,
+        path: &str,
+        recursive: bool,
+        tags: &[Tag],
+    ) -> Result<HashSet<String>, Box<dyn Error>>;
+
+    /// Lists the tags of a single parameter
+    async fn tags_of(&self, name: &str) -> Result<Vec<Tag>, Box<dyn Error>>;
+}
+
+#[async_trait]
+impl TagClient for aws_sdk_ssm::Client {
+    async fn tagged(
+        &self,
+        path: &str,
+        recursive: bool,
+        tags: &[Tag],
+    ) -> Result<HashSet<String>, Box<dyn Error>> {
+        let mut filters = vec![ParameterStringFilter::builder()
+            .key("Path")
+            .option(if recursive { "Recursive" } else { "OneLevel" })
+            .values(path)
+            .build()];
+        filters.extend(tags.iter().map(ParameterStringFilter::from));
+
+        let mut names = HashSet::new();
+        let mut next = None;
+
+        loop {
+            let resp = self
+                .describe_parameters()
+                .set_parameter_filters(Some(filters.clone()))
+                .set_next_token(next)
+                .send()
+                .await
+                .map_err(Box::new)?;
+
+            names.extend(
+                resp.parameters
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|parameter| parameter.name),
+            );
+
+            next = resp.next_token;
+
+            if next.is_none() {
+                break;
+            }
+        }
+
+        Ok(names)
+    }
+
+    async fn tags_of(&self, name: &str) -> Result<Vec<Tag>, Box<dyn Error>> {
+        let resp = self
+            .list_tags_for_resource()
+            .resource_type(ResourceTypeForTagging::Parameter)
+            .resource_id(name)
+            .send()
+            .await
+            .map_err(Box::new)?;
+
+        Ok(resp
+            .tag_list
+            .unwrap_or_default()
+            .into_iter()
+            .map(Tag::from)
+            .collect())
+    }
+}
+
+/// Fills in the tags of parameters read from SSM, so that `copy` and `restore` can attach them
+/// again. SSM only lists the tags of one parameter at a time, so a few are fetched at once.
+pub async fn fetch_tags<T>(client: &T, params: &mut [Param]) -> Result<(), Box<dyn Error>>
+where
+    T: TagClient,
+{
+    let metas = params
+        .iter_mut()
+        .filter_map(|param| param.meta.as_mut())
+        .collect::<Vec<_>>();
+
+    let tags = stream::iter(metas.iter())
+        .map(|meta| client.tags_of(&meta.name))
+        .buffered(CONCURRENCY)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    for (meta, tags) in metas.into_iter().zip(tags) {
+        meta.tags = tags;
+    }
+
+    Ok(())
+}
+
+/// Drops the parameters in a bag that do not carry all of the tags
+pub async fn keep_tagged<T>(
+    client: &T,
+    bag: &mut ParamBag,
+    tags: &[Tag],
+) -> Result<(), Box<dyn Error>>
+where
+    T: TagClient,
+{
+    let path = bag.prefix.trim_end_matches('/');
+    let names = client
+        .tagged(
+            if path.is_empty() { "/" } else { path },
+            bag.recursive,
+            tags,
+        )
+        .await?;
+
+    debug!(?names, "Found tagged parameters");
+
+    bag.params
+        .retain(|param| matches!(&param.meta, Some(meta) if names.contains(&meta.name)));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::tests::{param, ssm_bag};
+
+    struct TaggedClient;
+
+    #[async_trait]
+    impl TagClient for TaggedClient {
+        async fn tagged(
+            &self,
+            path: &str,
+            _recursive: bool,
+            tags: &[Tag],
+        ) -> Result<HashSet<String>, Box<dyn Error>> {
+            assert_eq!("/app", path);
+
+            Ok(tags
+                .iter()
+                .map(|tag| format!("{}/{}", path, tag.value))
+                .collect())
+        }
+
+        async fn tags_of(&self, name: &str) -> Result<Vec<Tag>, Box<dyn Error>> {
+            Ok(vec![Tag {
+                key: "service".to_string(),
+                value: name.trim_start_matches("/app/").to_string(),
+            }])
+        }
+    }
+
+    #[test]
+    fn test_parses_tags() {
+        assert_eq!(
+            Tag {
+                key: "owner".to_string(),
+                value: "team=platform".to_string(),
+            },
+            "owner=team=platform".parse().unwrap()
+        );
+        assert_eq!("", "env=".parse::<Tag>().unwrap().value);
+        assert!("owner".parse::<Tag>().is_err());
+        assert!("=x".parse::<Tag>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_keeps_tagged_parameters() {
+        let mut bag = ssm_bag(&[("/app/api", "x"), ("/app/worker", "x")]);
+
+        keep_tagged(&TaggedClient, &mut bag, &["service=api".parse().unwrap()])
+            .await
+            .unwrap();
+
+        assert_eq!(1, bag.params.len());
+        assert_eq!("/app/api", bag.params[0].key);
+    }
+
+    #[tokio::test]
+    async fn test_fetches_tags_of_each_parameter() {
+        let mut bag = ssm_bag(&[("/app/api", "x"), ("/app/worker", "x")]);
+        bag.params.push(param("LOCAL", "x"));
+
+        fetch_tags(&TaggedClient, &mut bag.params).await.unwrap();
+
+        assert_eq!(
+            vec![
+                vec!["service=api".parse().unwrap()],
+                vec!["service=worker".parse::<Tag>().unwrap()],
+            ],
+            bag.params
+                .iter()
+                .filter_map(|param| param.meta.as_ref())
+                .map(|meta| meta.tags.clone())
+                .collect::<Vec<_>>()
+        );
+    }
+}
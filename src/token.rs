@@ -0,0 +1,322 @@
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::secret::Secret;
+
+/// Environment variable checked for an MFA token before falling back to a prompt
+pub const TOKEN_ENV_VAR: &str = "ENVFMT_MFA_TOKEN";
+
+/// Length of a TOTP time step in seconds
+const TOTP_STEP: u64 = 30;
+
+/// Where an MFA token comes from
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenSource {
+    /// A token supplied directly, e.g. with `--mfa-token`
+    Value(Secret),
+    /// A token read from an environment variable
+    Env(String),
+    /// A shell command that prints a token
+    Command(String),
+    /// A file holding a base32 TOTP secret or an `otpauth://` URI
+    TotpFile(PathBuf),
+    /// A TOTP secret held in the OS keyring under this service name
+    TotpKeyring(String),
+    /// Ask on the terminal
+    Prompt,
+}
+
+#[derive(Debug)]
+pub enum TokenError {
+    Io(io::Error),
+    Command { command: String, reason: String },
+    MissingEnv(String),
+    InvalidSecret,
+    UnsupportedKeyring,
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenError::Io(err) => write!(f, "Failed to read MFA token: {}", err),
+            TokenError::Command { command, reason } => {
+                write!(f, "MFA token command {} failed: {}", command, reason)
+            }
+            TokenError::MissingEnv(var) => write!(f, "{} is not set", var),
+            TokenError::InvalidSecret => write!(f, "TOTP secret is not valid base32"),
+            TokenError::UnsupportedKeyring => write!(
+                f,
+                "The OS keyring is only supported on macOS and Linux, use --mfa-totp-file or --mfa-command instead"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+impl From<io::Error> for TokenError {
+    fn from(err: io::Error) -> Self {
+        TokenError::Io(err)
+    }
+}
+
+impl TokenSource {
+    /// Picks the token source from the command line options, which are mutually exclusive,
+    /// falling back to the `ENVFMT_MFA_TOKEN` environment variable and then a prompt
+    pub fn select(
+        token: Option<String>,
+        command: Option<String>,
+        totp_file: Option<String>,
+        totp_keyring: Option<String>,
+    ) -> Self {
+        if let Some(token) = token {
+            TokenSource::Value(token.into())
+        } else if let Some(command) = command {
+            TokenSource::Command(command)
+        } else if let Some(file) = totp_file {
+            TokenSource::TotpFile(PathBuf::from(file))
+        } else if let Some(service) = totp_keyring {
+            TokenSource::TotpKeyring(service)
+        } else if std::env::var_os(TOKEN_ENV_VAR).is_some() {
+            TokenSource::Env(TOKEN_ENV_VAR.to_string())
+        } else {
+            TokenSource::Prompt
+        }
+    }
+
+    /// Whether the source can produce more than one token. Fixed tokens can only be used once,
+    /// after which further tokens are prompted for.
+    pub fn is_reusable(&self) -> bool {
+        !matches!(self, TokenSource::Value(_) | TokenSource::Env(_))
+    }
+
+    /// Produces a token. This may block on a command or the terminal.
+    pub fn token(&self) -> Result<String, TokenError> {
+        match self {
+            TokenSource::Value(token) => Ok(token.expose().to_string()),
+            TokenSource::Env(var) => std::env::var(var)
+                .map(|t| t.trim().to_string())
+                .map_err(|_| TokenError::MissingEnv(var.clone())),
+            TokenSource::Command(command) => run_command(command),
+            TokenSource::TotpFile(path) => totp_now(&std::fs::read_to_string(path)?),
+            TokenSource::TotpKeyring(service) => totp_now(&keyring_secret(service)?),
+            TokenSource::Prompt => prompt("MFA token is required: "),
+        }
+    }
+}
+
+fn shell(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    }
+}
+
+fn run_command(command: &str) -> Result<String, TokenError> {
+    let output = shell(command).output()?;
+
+    if !output.status.success() {
+        return Err(TokenError::Command {
+            command: command.to_string(),
+            reason: format!(
+                "{} {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        });
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Reads a secret from the OS keyring with the platform's command line tool, `security` on macOS
+/// and `secret-tool` elsewhere. Windows has no such tool.
+fn keyring_secret(service: &str) -> Result<String, TokenError> {
+    if cfg!(windows) {
+        return Err(TokenError::UnsupportedKeyring);
+    }
+
+    let (program, args) = if cfg!(target_os = "macos") {
+        (
+            "security",
+            vec!["find-generic-password", "-w", "-s", service],
+        )
+    } else {
+        ("secret-tool", vec!["lookup", "service", service])
+    };
+
+    let output = Command::new(program).args(&args).output()?;
+
+    if !output.status.success() {
+        return Err(TokenError::Command {
+            command: program.to_string(),
+            reason: format!("no secret found for {}", service),
+        });
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Prompts on the controlling terminal so that the prompt does not end up in redirected output
+/// and the answer is not read from piped input
+pub fn prompt(message: &str) -> Result<String, TokenError> {
+    let mut input = String::new();
+
+    match OpenOptions::new().read(true).write(true).open("/dev/tty") {
+        Ok(mut tty) => {
+            tty.write_all(message.as_bytes())?;
+            tty.flush()?;
+            BufReader::new(tty).read_line(&mut input)?;
+        }
+        Err(_) => {
+            eprint!("{}", message);
+            io::stderr().flush()?;
+            io::stdin().read_line(&mut input)?;
+        }
+    }
+
+    Ok(input.trim().to_string())
+}
+
+fn totp_now(secret: &str) -> Result<String, TokenError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    totp(secret, now, 6)
+}
+
+/// Generates an RFC 6238 TOTP code using HMAC-SHA1 and a 30 second step. The secret may be a
+/// bare base32 string or an `otpauth://` URI with a `secret` parameter.
+pub fn totp(secret: &str, time: u64, digits: u32) -> Result<String, TokenError> {
+    let secret = secret.trim();
+    let secret = match secret.strip_prefix("otpauth://") {
+        Some(uri) => uri
+            .split(['?', '&'])
+            .find_map(|part| part.strip_prefix("secret="))
+            .ok_or(TokenError::InvalidSecret)?,
+        None => secret,
+    };
+
+    let key = base32_decode(secret).ok_or(TokenError::InvalidSecret)?;
+    let mut mac = Hmac::<Sha1>::new_from_slice(&key).map_err(|_| TokenError::InvalidSecret)?;
+    mac.update(&(time / TOTP_STEP).to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let code = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]) % 10u32.pow(digits);
+
+    Ok(format!("{:0width$}", code, width = digits as usize))
+}
+
+/// Decodes RFC 4648 base32, ignoring case, whitespace and padding
+fn base32_decode(input: &str) -> Option<Vec<u8>> {
+    let mut out = vec![];
+    let mut buffer: u64 = 0;
+    let mut bits = 0;
+
+    for c in input.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = match c.to_ascii_uppercase() {
+            c @ 'A'..='Z' => c as u64 - 'A' as u64,
+            c @ '2'..='7' => c as u64 - '2' as u64 + 26,
+            _ => return None,
+        };
+
+        buffer = (buffer << 5) | value;
+        bits += 5;
+
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    if out.is_empty() {
+        None
+    } else {
+        Some(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // "12345678901234567890" from the RFC 6238 test vectors
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn test_decodes_base32() {
+        assert_eq!(
+            Some(b"12345678901234567890".to_vec()),
+            base32_decode(RFC_SECRET)
+        );
+        assert_eq!(
+            Some(b"foobar".to_vec()),
+            base32_decode("mzxw 6ytb oi======")
+        );
+        assert_eq!(None, base32_decode("not base32!"));
+    }
+
+    #[test]
+    fn test_generates_rfc_6238_codes() {
+        assert_eq!("94287082", totp(RFC_SECRET, 59, 8).unwrap());
+        assert_eq!("07081804", totp(RFC_SECRET, 1111111109, 8).unwrap());
+        assert_eq!("005924", totp(RFC_SECRET, 1234567890, 6).unwrap());
+    }
+
+    #[test]
+    fn test_reads_secret_from_otpauth_uri() {
+        let uri = format!("otpauth://totp/aws:me?secret={}&issuer=aws", RFC_SECRET);
+
+        assert_eq!("287082", totp(&uri, 59, 6).unwrap());
+    }
+
+    #[test]
+    fn test_prefers_explicit_sources() {
+        assert_eq!(
+            TokenSource::Value("123456".into()),
+            TokenSource::select(
+                Some("123456".to_string()),
+                Some("cmd".to_string()),
+                None,
+                None
+            )
+        );
+        assert_eq!(
+            TokenSource::Command("cmd".to_string()),
+            TokenSource::select(None, Some("cmd".to_string()), Some("f".to_string()), None)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_runs_token_commands() {
+        assert_eq!(
+            "123456",
+            TokenSource::Command("echo ' 123456 '".to_string())
+                .token()
+                .unwrap()
+        );
+        assert!(TokenSource::Command("exit 1".to_string()).token().is_err());
+    }
+}
@@ -0,0 +1,610 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::output;
+use crate::params::{Param, ParamBag};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Case {
+    Upper,
+    Lower,
+    Preserve,
+}
+
+impl FromStr for Case {
+    type Err = TransformError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "upper" => Ok(Case::Upper),
+            "lower" => Ok(Case::Lower),
+            "preserve" => Ok(Case::Preserve),
+            _ => Err(TransformError::UnknownCase),
+        }
+    }
+}
+
+/// What to do with keys that are not valid shell identifiers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NameStrategy {
+    Sanitize,
+    Skip,
+    Fail,
+}
+
+impl FromStr for NameStrategy {
+    type Err = TransformError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sanitize" => Ok(NameStrategy::Sanitize),
+            "skip" => Ok(NameStrategy::Skip),
+            "fail" => Ok(NameStrategy::Fail),
+            _ => Err(TransformError::UnknownStrategy),
+        }
+    }
+}
+
+impl NameStrategy {
+    /// Applies the strategy to the keys of a bag. Sanitizing fails when two keys end up with the
+    /// same name, rather than letting one value silently replace the other.
+    pub fn apply(&self, bag: &mut ParamBag) -> Result<(), TransformError> {
+        match self {
+            NameStrategy::Sanitize => {
+                let mut originals = HashMap::new();
+
+                for param in bag.params.iter_mut() {
+                    let original = param.key.clone();
+
+                    if !is_valid_name(&param.key) {
+                        let sanitized = sanitize_name(&param.key);
+                        output::warning(format!(
+                            "Renamed {} to {} as it is not a valid variable name",
+                            param.key, sanitized
+                        ));
+                        param.key = sanitized;
+                    }
+
+                    if let Some(first) = originals.insert(param.key.clone(), original.clone()) {
+                        return Err(TransformError::NameCollision {
+                            first,
+                            second: original,
+                            name: param.key.clone(),
+                        });
+                    }
+                }
+            }
+            NameStrategy::Skip => bag.params.retain(|param| {
+                let valid = is_valid_name(&param.key);
+
+                if !valid {
+                    output::warning(format!(
+                        "Skipping {} as it is not a valid variable name",
+                        param.key
+                    ));
+                }
+
+                valid
+            }),
+            NameStrategy::Fail => {
+                if let Some(param) = bag.params.iter().find(|p| !is_valid_name(&p.key)) {
+                    return Err(TransformError::InvalidName(param.key.clone()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// How to output the values of `StringList` parameters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListStyle {
+    /// As SSM stores them, joined by commas
+    Join,
+    /// As a JSON array of strings
+    Json,
+    /// As one key per item, named `KEY_0`, `KEY_1` and so on
+    Expand,
+}
+
+impl FromStr for ListStyle {
+    type Err = TransformError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "join" => Ok(ListStyle::Join),
+            "json" => Ok(ListStyle::Json),
+            "expand" => Ok(ListStyle::Expand),
+            _ => Err(TransformError::UnknownListStyle),
+        }
+    }
+}
+
+impl ListStyle {
+    /// Applies the style to the `StringList` values of a bag. Expanding fails when an item's key
+    /// is already taken by another parameter, rather than outputting both.
+    pub fn apply(&self, bag: &mut ParamBag) -> Result<(), TransformError> {
+        match self {
+            ListStyle::Join => {}
+            ListStyle::Json => {
+                for param in bag.params.iter_mut().filter(|param| param.is_list()) {
+                    param.value =
+                        serde_json::to_string(&param.value.expose().split(',').collect::<Vec<_>>())
+                            .unwrap_or_default()
+                            .into();
+                }
+            }
+            ListStyle::Expand => {
+                // The key of the parameter each key output so far came from
+                let mut origins = HashMap::new();
+                let mut params = vec![];
+
+                for param in std::mem::take(&mut bag.params) {
+                    let origin = param.key.clone();
+                    let expanded = if param.is_list() {
+                        param
+                            .value
+                            .expose()
+                            .split(',')
+                            .enumerate()
+                            .map(|(i, item)| Param {
+                                key: format!("{}_{}", param.key, i),
+                                value: item.into(),
+                                meta: param.meta.clone(),
+                            })
+                            .collect()
+                    } else {
+                        vec![param]
+                    };
+
+                    for param in expanded {
+                        match origins.insert(param.key.clone(), origin.clone()) {
+                            Some(first) if first != param.key || origin != param.key => {
+                                return Err(TransformError::ListCollision {
+                                    first,
+                                    second: origin,
+                                    name: param.key,
+                                });
+                            }
+                            _ => params.push(param),
+                        }
+                    }
+                }
+
+                bag.params = params;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A valid name starts with a letter or underscore followed by letters, digits or underscores
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+/// Replaces every invalid character with `_`, and prepends `_` to names starting with a digit
+pub fn sanitize_name(name: &str) -> String {
+    let sanitized = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+
+    match sanitized.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => sanitized,
+        _ => "_".to_string() + &sanitized,
+    }
+}
+
+#[derive(Debug)]
+pub enum TransformError {
+    UnknownCase,
+    UnknownStrategy,
+    UnknownListStyle,
+    InvalidName(String),
+    NameCollision {
+        first: String,
+        second: String,
+        name: String,
+    },
+    ListCollision {
+        first: String,
+        second: String,
+        name: String,
+    },
+    InvalidRename {
+        line: usize,
+        content: String,
+    },
+    DuplicateRename {
+        line: usize,
+        key: String,
+    },
+}
+
+impl fmt::Display for TransformError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransformError::UnknownCase => write!(f, "Not a valid case style"),
+            TransformError::UnknownStrategy => write!(f, "Not a valid name strategy"),
+            TransformError::UnknownListStyle => write!(f, "Not a valid list style"),
+            TransformError::InvalidName(name) => {
+                write!(f, "{} is not a valid environment variable name", name)
+            }
+            TransformError::NameCollision {
+                first,
+                second,
+                name,
+            } => write!(
+                f,
+                "{} and {} would both be written as {}, use --invalid-names skip or filter one of them out",
+                first, second, name
+            ),
+            TransformError::ListCollision {
+                first,
+                second,
+                name,
+            } => write!(
+                f,
+                "{} and {} would both be written as {}, use --string-lists join or json or filter one of them out",
+                first, second, name
+            ),
+            TransformError::InvalidRename { line, content } => write!(
+                f,
+                "Invalid rename on line {}, expected FROM=TO but found {}",
+                line, content
+            ),
+            TransformError::DuplicateRename { line, key } => write!(
+                f,
+                "{} is renamed again on line {}, renames must be one to one so they can be reversed",
+                key, line
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TransformError {}
+
+/// Transforms a key as it moves between Parameter Store and a local format. The steps are
+/// applied in order: strip prefix, replace separators, change case and then add prefix. A key
+/// found in the rename map skips all of the steps and is replaced verbatim. The rename map goes
+/// from Parameter Store to local keys, so writing uses the inverted transform.
+#[derive(Debug, Clone)]
+pub struct KeyTransform {
+    pub strip_prefix: Option<String>,
+    pub add_prefix: Option<String>,
+    pub replace_separators: bool,
+    pub case: Case,
+    pub renames: HashMap<String, String>,
+}
+
+impl Default for KeyTransform {
+    fn default() -> Self {
+        KeyTransform::with_case(Case::Upper)
+    }
+}
+
+impl KeyTransform {
+    pub fn with_case(case: Case) -> Self {
+        KeyTransform {
+            strip_prefix: None,
+            add_prefix: None,
+            replace_separators: false,
+            case,
+            renames: HashMap::new(),
+        }
+    }
+
+    /// Swaps the direction of the rename map, for writing local keys back to Parameter Store
+    pub fn inverted(mut self) -> Self {
+        self.renames = self
+            .renames
+            .into_iter()
+            .map(|(from, to)| (to, from))
+            .collect();
+        self
+    }
+
+    pub fn apply(&self, key: &str) -> String {
+        if let Some(renamed) = self.renames.get(key) {
+            return renamed.clone();
+        }
+
+        let mut out = match self.strip_prefix {
+            Some(ref prefix) => key.strip_prefix(prefix.as_str()).unwrap_or(key),
+            None => key,
+        }
+        .to_string();
+
+        if self.replace_separators {
+            out = out.replace(['-', '.'], "_");
+        }
+
+        out = match self.case {
+            Case::Upper => out.to_uppercase(),
+            Case::Lower => out.to_lowercase(),
+            Case::Preserve => out,
+        };
+
+        match self.add_prefix {
+            Some(ref prefix) => prefix.clone() + &out,
+            None => out,
+        }
+    }
+}
+
+/// Parses a rename map with one `FROM=TO` pair per line, where `FROM` is the name in Parameter
+/// Store and `TO` the local key. Blank lines and lines starting with `#` are ignored. Each name
+/// may only appear once on either side, so that the map can be inverted for writing.
+pub fn parse_rename_map(input: &str) -> Result<HashMap<String, String>, TransformError> {
+    let mut renames = HashMap::new();
+    let mut targets = HashSet::new();
+
+    for (i, line) in input.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match line.split_once('=') {
+            Some((from, to)) if !from.trim().is_empty() && !to.trim().is_empty() => {
+                let (from, to) = (from.trim(), to.trim());
+
+                for (key, new) in [
+                    (from, !renames.contains_key(from)),
+                    (to, targets.insert(to)),
+                ] {
+                    if !new {
+                        return Err(TransformError::DuplicateRename {
+                            line: i + 1,
+                            key: key.to_string(),
+                        });
+                    }
+                }
+
+                renames.insert(from.to_string(), to.to_string());
+            }
+            _ => {
+                return Err(TransformError::InvalidRename {
+                    line: i + 1,
+                    content: line.to_string(),
+                })
+            }
+        }
+    }
+
+    Ok(renames)
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_ssm::model::ParameterType;
+
+    use super::*;
+    use crate::params::tests::{self, meta};
+    use crate::params::ParamMeta;
+
+    #[test]
+    fn test_default_uppercases() {
+        assert_eq!("PARAM_KEY", KeyTransform::default().apply("param_key"));
+    }
+
+    #[test]
+    fn test_applies_steps_in_order() {
+        let transform = KeyTransform {
+            strip_prefix: Some("legacy.".to_string()),
+            add_prefix: Some("APP_".to_string()),
+            replace_separators: true,
+            case: Case::Upper,
+            renames: HashMap::new(),
+        };
+
+        assert_eq!("APP_DB_HOST_NAME", transform.apply("legacy.db-host.name"));
+        assert_eq!("APP_OTHER_KEY", transform.apply("other.key"));
+    }
+
+    #[test]
+    fn test_renames_are_verbatim() {
+        let mut transform = KeyTransform::with_case(Case::Lower);
+        transform
+            .renames
+            .insert("DATABASE_URL".to_string(), "DB_Url".to_string());
+
+        assert_eq!("DB_Url", transform.apply("DATABASE_URL"));
+        assert_eq!("other", transform.apply("OTHER"));
+    }
+
+    #[test]
+    fn test_preserve_round_trips_mixed_case() {
+        let write = KeyTransform {
+            strip_prefix: Some("APP_".to_string()),
+            ..KeyTransform::with_case(Case::Preserve)
+        };
+        let read = KeyTransform {
+            add_prefix: Some("APP_".to_string()),
+            ..KeyTransform::with_case(Case::Preserve)
+        };
+
+        for key in ["APP_myKey", "APP_Mixed_Case", "APP_lower"] {
+            assert_eq!(key, read.apply(&write.apply(key)));
+        }
+    }
+
+    fn bag(keys: &[&str]) -> ParamBag {
+        tests::bag(&keys.iter().map(|key| (*key, "value")).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_validates_names() {
+        assert!(is_valid_name("MY_KEY"));
+        assert!(is_valid_name("_private1"));
+        assert!(!is_valid_name("MY-KEY"));
+        assert!(!is_valid_name("1PARAM"));
+        assert!(!is_valid_name("MY.KEY"));
+        assert!(!is_valid_name(""));
+    }
+
+    #[test]
+    fn test_sanitizes_names() {
+        assert_eq!("MY_KEY", sanitize_name("MY-KEY"));
+        assert_eq!("_1PARAM", sanitize_name("1PARAM"));
+        assert_eq!("A_B_C", sanitize_name("A.B C"));
+        assert_eq!("_", sanitize_name(""));
+    }
+
+    #[test]
+    fn test_sanitize_strategy_rewrites_keys() {
+        let mut bag = bag(&["GOOD", "BAD-KEY"]);
+        NameStrategy::Sanitize.apply(&mut bag).unwrap();
+
+        assert_eq!("GOOD", bag.params[0].key);
+        assert_eq!("BAD_KEY", bag.params[1].key);
+    }
+
+    #[test]
+    fn test_sanitize_strategy_rejects_collisions() {
+        for keys in [
+            ["MY-KEY", "MY_KEY"],
+            ["MY_KEY", "MY-KEY"],
+            ["MY.KEY", "MY-KEY"],
+        ]
+        .iter()
+        {
+            let mut bag = bag(keys);
+
+            match NameStrategy::Sanitize.apply(&mut bag) {
+                Err(TransformError::NameCollision {
+                    first,
+                    second,
+                    name,
+                }) => {
+                    assert_eq!(keys, &[first.as_str(), second.as_str()]);
+                    assert_eq!("MY_KEY", name);
+                }
+                other => panic!("Expected name collision error, found {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_skip_strategy_drops_keys() {
+        let mut bag = bag(&["GOOD", "BAD-KEY", "1BAD"]);
+        NameStrategy::Skip.apply(&mut bag).unwrap();
+
+        assert_eq!(1, bag.params.len());
+        assert_eq!("GOOD", bag.params[0].key);
+    }
+
+    #[test]
+    fn test_fail_strategy_errors() {
+        let mut bag = bag(&["GOOD", "BAD.KEY"]);
+
+        match NameStrategy::Fail.apply(&mut bag) {
+            Err(TransformError::InvalidName(name)) => assert_eq!("BAD.KEY", name),
+            other => panic!("Expected invalid name error, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_formats_string_lists() {
+        let list_bag = || {
+            let mut bag = bag(&["HOSTS", "NAME"]);
+            bag.params[0].value = "a.example.com,b.example.com".into();
+            bag.params[0].meta = Some(ParamMeta {
+                param_type: Some(ParameterType::StringList),
+                ..meta("/app/hosts")
+            });
+            bag
+        };
+
+        let mut json = list_bag();
+        ListStyle::Json.apply(&mut json).unwrap();
+        assert_eq!(r#"["a.example.com","b.example.com"]"#, json.params[0].value);
+        assert_eq!("value", json.params[1].value);
+
+        let mut bag = list_bag();
+        ListStyle::Expand.apply(&mut bag).unwrap();
+        assert_eq!(
+            vec![
+                ("HOSTS_0", "a.example.com"),
+                ("HOSTS_1", "b.example.com"),
+                ("NAME", "value"),
+            ],
+            bag.params
+                .iter()
+                .map(|p| (p.key.as_str(), p.value.expose()))
+                .collect::<Vec<_>>()
+        );
+
+        let mut taken = list_bag();
+        taken.params[1].key = "HOSTS_1".to_string();
+        match ListStyle::Expand.apply(&mut taken) {
+            Err(TransformError::ListCollision {
+                first,
+                second,
+                name,
+            }) => assert_eq!(
+                ("HOSTS", "HOSTS_1", "HOSTS_1"),
+                (first.as_str(), second.as_str(), name.as_str())
+            ),
+            other => panic!("Expected list collision error, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parses_rename_map() {
+        let renames = parse_rename_map("# comment\n\nold_name = NEW_NAME\nA=B\n").unwrap();
+
+        assert_eq!(2, renames.len());
+        assert_eq!("NEW_NAME", renames["old_name"]);
+        assert_eq!("B", renames["A"]);
+    }
+
+    #[test]
+    fn test_rejects_renames_that_are_not_one_to_one() {
+        for (input, key) in [("A=X\nB=X\n", "X"), ("A=X\nA=Y\n", "A")].iter() {
+            match parse_rename_map(input) {
+                Err(TransformError::DuplicateRename { line, key: found }) => {
+                    assert_eq!((2, *key), (line, found.as_str()))
+                }
+                other => panic!("Expected duplicate rename error, found {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_inverted_renames_round_trip() {
+        let read = KeyTransform {
+            renames: parse_rename_map("db_host_legacy=DATABASE_HOST\n").unwrap(),
+            ..KeyTransform::with_case(Case::Upper)
+        };
+        let write = KeyTransform {
+            case: Case::Lower,
+            ..read.clone()
+        }
+        .inverted();
+
+        assert_eq!("DATABASE_HOST", read.apply("db_host_legacy"));
+        assert_eq!("db_host_legacy", write.apply("DATABASE_HOST"));
+        assert_eq!("other", write.apply("OTHER"));
+    }
+
+    #[test]
+    fn test_rejects_invalid_rename_lines() {
+        match parse_rename_map("A=B\nmissing\n") {
+            Err(TransformError::InvalidRename { line, .. }) => assert_eq!(2, line),
+            other => panic!("Expected invalid rename error, found {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,405 @@
+use async_trait::async_trait;
+use aws_sdk_ssm::model::{ParameterMetadata, ParameterStringFilter};
+use aws_smithy_types::date_time::Format;
+use serde_json::{json, Value};
+use tracing::debug;
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::params::{Param, ParamBag, ParamMeta};
+use crate::references::ResolveClient;
+
+/// Maximum number of names accepted by a single `GetParameters` request
+const BATCH_SIZE: usize = 10;
+
+#[async_trait]
+pub trait DescribeClient {
+    /// Describes the parameters below a path, without fetching their values
+    async fn describe(
+        &self,
+        path: &str,
+        recursive: bool,
+    ) -> Result<Vec<ParameterMetadata>, Box<dyn Error>>;
+}
+
+#[async_trait]
+impl DescribeClient for aws_sdk_ssm::Client {
+    async fn describe(
+        &self,
+        path: &str,
+        recursive: bool,
+    ) -> Result<Vec<ParameterMetadata>, Box<dyn Error>> {
+        let filter = ParameterStringFilter::builder()
+            .key("Path")
+            .option(if recursive { "Recursive" } else { "OneLevel" })
+            .values(path)
+            .build();
+
+        let mut parameters = vec![];
+        let mut next = None;
+
+        loop {
+            let resp = self
+                .describe_parameters()
+                .parameter_filters(filter.clone())
+                .set_next_token(next)
+                .send()
+                .await
+                .map_err(Box::new)?;
+
+            parameters.extend(resp.parameters.unwrap_or_default());
+            next = resp.next_token;
+
+            if next.is_none() {
+                break;
+            }
+        }
+
+        Ok(parameters)
+    }
+}
+
+/// Finds the parameters `ls` prints. Values are only fetched, and decrypted, when they are going
+/// to be shown.
+pub async fn list<T>(
+    client: &T,
+    path: &str,
+    recursive: bool,
+    show_values: bool,
+) -> Result<ParamBag, Box<dyn Error>>
+where
+    T: DescribeClient + ResolveClient,
+{
+    let mut bag = ParamBag::new(path).with_recursive(recursive);
+    let path = bag.prefix.trim_end_matches('/');
+
+    bag.params = client
+        .describe(if path.is_empty() { "/" } else { path }, recursive)
+        .await?
+        .into_iter()
+        .filter_map(|parameter| {
+            let name = parameter.name?;
+
+            Some(Param {
+                key: name.clone(),
+                value: Default::default(),
+                meta: Some(ParamMeta {
+                    name,
+                    param_type: parameter.r#type,
+                    version: parameter.version,
+                    arn: None,
+                    data_type: parameter.data_type,
+                    last_modified: parameter.last_modified_date,
+                    tags: vec![],
+                }),
+            })
+        })
+        .collect();
+
+    if show_values {
+        let names = bag
+            .params
+            .iter()
+            .map(|param| param.key.clone())
+            .collect::<Vec<_>>();
+
+        for chunk in names.chunks(BATCH_SIZE) {
+            debug!(?chunk, "Fetching values");
+            let mut values = client.get_values(chunk).await?;
+
+            for param in bag
+                .params
+                .iter_mut()
+                .filter(|param| chunk.contains(&param.key))
+            {
+                if let Some(value) = values.remove(&param.key) {
+                    param.value = value.into();
+                }
+            }
+        }
+    }
+
+    Ok(bag)
+}
+
+/// The parameters below a path, grouped by path segment for `ls`
+pub struct ParamTree<'a> {
+    root: String,
+    node: Node<'a>,
+    show_values: bool,
+}
+
+#[derive(Default)]
+struct Node<'a> {
+    param: Option<&'a Param>,
+    children: BTreeMap<&'a str, Node<'a>>,
+}
+
+impl<'a> From<&'a ParamBag> for ParamTree<'a> {
+    fn from(bag: &'a ParamBag) -> Self {
+        let root = bag.prefix.trim_end_matches('/');
+        let mut node = Node::default();
+
+        for param in &bag.params {
+            let name = match &param.meta {
+                Some(meta) => meta.name.as_str(),
+                None => continue,
+            };
+            let relative = name.strip_prefix(root).unwrap_or(name);
+
+            let leaf = relative
+                .split('/')
+                .filter(|segment| !segment.is_empty())
+                .fold(&mut node, |node, segment| {
+                    node.children.entry(segment).or_default()
+                });
+            leaf.param = Some(param);
+        }
+
+        ParamTree {
+            root: if root.is_empty() { "/" } else { root }.to_string(),
+            node,
+            show_values: false,
+        }
+    }
+}
+
+impl<'a> ParamTree<'a> {
+    pub fn with_values(mut self, show_values: bool) -> Self {
+        self.show_values = show_values;
+        self
+    }
+
+    /// A flat list of the parameters in the tree, in path order
+    pub fn to_json(&self) -> Value {
+        let mut params = vec![];
+        self.node.collect(&mut params);
+
+        Value::Array(
+            params
+                .into_iter()
+                .filter_map(|param| {
+                    let meta = param.meta.as_ref()?;
+                    let mut entry = json!({
+                        "name": meta.name,
+                        "type": meta.param_type.as_ref().map(|t| t.as_str()),
+                        "version": meta.version,
+                        "last_modified": meta
+                            .last_modified
+                            .as_ref()
+                            .and_then(|t| t.fmt(Format::DateTime).ok()),
+                    });
+
+                    if self.show_values {
+                        entry["value"] = json!(param.value.expose());
+                    }
+
+                    Some(entry)
+                })
+                .collect(),
+        )
+    }
+}
+
+impl<'a> Node<'a> {
+    fn collect(&self, params: &mut Vec<&'a Param>) {
+        params.extend(self.param);
+
+        for child in self.children.values() {
+            child.collect(params);
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter, indent: &str, show_values: bool) -> fmt::Result {
+        let count = self.children.len();
+
+        for (i, (segment, child)) in self.children.iter().enumerate() {
+            let last = i + 1 == count;
+
+            write!(
+                f,
+                "\n{}{}{}",
+                indent,
+                if last { "└── " } else { "├── " },
+                segment
+            )?;
+
+            if let Some(meta) = child.param.and_then(|param| param.meta.as_ref()) {
+                if let Some(param_type) = &meta.param_type {
+                    write!(f, "  {}", param_type.as_str())?;
+                }
+
+                write!(f, "  v{}", meta.version)?;
+
+                if let Some(modified) = meta
+                    .last_modified
+                    .as_ref()
+                    .and_then(|t| t.fmt(Format::DateTime).ok())
+                {
+                    write!(f, "  {}", modified)?;
+                }
+
+                if let Some(param) = child.param.filter(|_| show_values) {
+                    write!(f, "  = {}", param.value.expose().escape_debug())?;
+                }
+            }
+
+            let indent = indent.to_string() + if last { "    " } else { "│   " };
+            child.write(f, &indent, show_values)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Display for ParamTree<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.root)?;
+        self.node.write(f, "", self.show_values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use aws_sdk_ssm::model::ParameterType;
+    use aws_smithy_types::DateTime;
+
+    use super::*;
+    use crate::params::tests::{meta, param};
+
+    #[derive(Default)]
+    struct ListingClient {
+        fetched: Mutex<Vec<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl DescribeClient for ListingClient {
+        async fn describe(
+            &self,
+            path: &str,
+            recursive: bool,
+        ) -> Result<Vec<ParameterMetadata>, Box<dyn Error>> {
+            assert_eq!(("/app", true), (path, recursive));
+
+            Ok((0..12)
+                .map(|i| {
+                    ParameterMetadata::builder()
+                        .name(format!("/app/{:02}", i))
+                        .r#type(ParameterType::SecureString)
+                        .version(i)
+                        .build()
+                })
+                .collect())
+        }
+    }
+
+    #[async_trait]
+    impl ResolveClient for ListingClient {
+        async fn get_values(
+            &self,
+            names: &[String],
+        ) -> Result<HashMap<String, String>, Box<dyn Error>> {
+            self.fetched.lock().unwrap().push(names.to_vec());
+
+            Ok(names
+                .iter()
+                .map(|name| (name.clone(), format!("value of {}", name)))
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lists_without_fetching_values() {
+        let client = ListingClient::default();
+
+        let bag = list(&client, "/app/", true, false).await.unwrap();
+        assert_eq!(12, bag.params.len());
+        assert_eq!(
+            3,
+            bag.params[3].meta.as_ref().map_or(0, |meta| meta.version)
+        );
+        assert_eq!("", bag.params[3].value);
+        assert!(client.fetched.lock().unwrap().is_empty());
+
+        let bag = list(&client, "/app", true, true).await.unwrap();
+        assert_eq!("value of /app/11", bag.params[11].value);
+        assert_eq!(
+            vec![10, 2],
+            client
+                .fetched
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|names| names.len())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    fn bag() -> ParamBag {
+        let mut bag = ParamBag::new("/app/");
+
+        for (name, param_type, version) in [
+            ("/app/prod/db_host", ParameterType::String, 3),
+            ("/app/prod/api_key", ParameterType::SecureString, 1),
+            ("/app/staging/db_host", ParameterType::String, 12),
+            ("/app/name", ParameterType::String, 1),
+        ] {
+            bag.params.push(Param {
+                meta: Some(ParamMeta {
+                    param_type: Some(param_type),
+                    version,
+                    last_modified: Some(DateTime::from_secs(1_600_000_000)),
+                    ..meta(name)
+                }),
+                ..param(name, "line one\nline two")
+            });
+        }
+
+        bag
+    }
+
+    #[test]
+    fn test_prints_a_tree_of_paths() {
+        let bag = bag();
+
+        assert_eq!(
+            "/app
+├── name  String  v1  2020-09-13T12:26:40Z
+├── prod
+│   ├── api_key  SecureString  v1  2020-09-13T12:26:40Z
+│   └── db_host  String  v3  2020-09-13T12:26:40Z
+└── staging
+    └── db_host  String  v12  2020-09-13T12:26:40Z",
+            ParamTree::from(&bag).to_string()
+        );
+        assert!(ParamTree::from(&bag)
+            .with_values(true)
+            .to_string()
+            .contains("db_host  String  v3  2020-09-13T12:26:40Z  = line one\\nline two"));
+    }
+
+    #[test]
+    fn test_lists_parameters_as_json() {
+        let bag = bag();
+
+        let json = ParamTree::from(&bag).to_json();
+        assert_eq!(4, json.as_array().unwrap().len());
+        assert_eq!(
+            json!({
+                "name": "/app/name",
+                "type": "String",
+                "version": 1,
+                "last_modified": "2020-09-13T12:26:40Z"
+            }),
+            json[0]
+        );
+
+        let json = ParamTree::from(&bag).with_values(true).to_json();
+        assert_eq!("line one\nline two", json[1]["value"]);
+    }
+}
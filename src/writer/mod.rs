@@ -1,42 +1,333 @@
-use aws_sdk_ssm::model::ParameterType;
+use aws_sdk_ssm::model::{ParameterType, ResourceTypeForTagging};
+use serde_json::json;
 
-use crate::params::ParamBag;
+use std::error::Error;
+
+use crate::output;
+use crate::params::{normalize_path, Param, ParamBag};
+use crate::tags::Tag;
+use crate::transform::{Case, KeyTransform};
+
+mod tier;
+mod value;
+
+pub use self::tier::{Policies, Tier};
+pub use self::value::ValueSource;
+
+/// The outcome of writing a bag of parameters
+#[derive(Debug, Default, PartialEq)]
+pub struct WriteSummary {
+    pub written: Vec<String>,
+    pub failed: Vec<(String, String)>,
+}
+
+impl WriteSummary {
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "written": self.written,
+            "failed": self
+                .failed
+                .iter()
+                .map(|(name, error)| json!({ "name": name, "error": error }))
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    /// Fails when any parameter could not be written, so that `write` exits with an error
+    pub fn check(&self) -> Result<(), String> {
+        if self.failed.is_empty() {
+            return Ok(());
+        }
+
+        Err(format!(
+            "Failed to write {} of {} parameters",
+            self.failed.len(),
+            self.failed.len() + self.written.len()
+        ))
+    }
+}
+
+/// The name a key is written to below a prefix
+fn name_below(transform: &KeyTransform, prefix: &str, key: &str) -> String {
+    format!("{}/{}", prefix, transform.apply(key))
+}
+
+/// The parameter a full name given to `set` or `get` stands for. Its last part is transformed
+/// like the keys of `write`, so both commands agree with `write` on names.
+pub fn full_name(transform: &KeyTransform, name: &str) -> String {
+    let path = normalize_path(name);
+    let (prefix, key) = path.rsplit_once('/').unwrap_or(("", &path));
+
+    name_below(transform, prefix, key)
+}
+
+/// The name a parameter below one path is copied to below another
+pub fn copied_name(from: &str, to: &str, name: &str) -> String {
+    let from = normalize_path(from);
+    let to = normalize_path(to);
+    let below = name
+        .strip_prefix(from.trim_end_matches('/'))
+        .unwrap_or(name);
+
+    format!("{}{}", to.trim_end_matches('/'), below)
+}
 
 pub struct Writer {
     client: aws_sdk_ssm::Client,
     force: bool,
+    secure: bool,
+    tags: Vec<Tag>,
+    /// Keys written as a `StringList`
+    lists: Vec<String>,
+    description: Option<String>,
+    tier: Option<Tier>,
+    policies: Policies,
+    transform: KeyTransform,
 }
 
 impl Writer {
     pub fn new(client: aws_sdk_ssm::Client, force: bool) -> Self {
-        Writer { client, force }
-    }
-
-    pub async fn write(&self, bag: &ParamBag) -> Option<()> {
-        for param in bag.params.iter() {
-            match self
-                .client
-                .put_parameter()
-                .name(format!("{}/{}", bag.prefix, param.key.to_lowercase()))
-                .overwrite(self.force)
-                .set_type(Some(ParameterType::String))
-                .value(param.value.to_string())
+        Writer {
+            client,
+            force,
+            secure: false,
+            tags: vec![],
+            lists: vec![],
+            description: None,
+            tier: None,
+            policies: Policies::default(),
+            transform: KeyTransform::with_case(Case::Lower),
+        }
+    }
+
+    pub fn with_transform(mut self, transform: KeyTransform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    /// Write values as `SecureString` instead of `String`
+    pub fn with_secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// Tags to attach to every parameter written
+    pub fn with_tags(mut self, tags: Vec<Tag>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    /// Keys whose comma separated values are written as a `StringList`
+    pub fn with_lists(mut self, lists: Vec<String>) -> Self {
+        self.lists = lists;
+        self
+    }
+
+    /// The type a key is written as
+    pub fn param_type(&self, key: &str) -> ParameterType {
+        if self.lists.iter().any(|list| list == key) {
+            ParameterType::StringList
+        } else if self.secure {
+            ParameterType::SecureString
+        } else {
+            ParameterType::String
+        }
+    }
+
+    /// Description to store with every parameter written
+    pub fn with_description(mut self, description: Option<String>) -> Self {
+        self.description = description;
+        self
+    }
+
+    /// Tier to store values in. Left to SSM when not given, unless a value needs Advanced.
+    pub fn with_tier(mut self, tier: Option<Tier>) -> Self {
+        self.tier = tier;
+        self
+    }
+
+    pub fn with_policies(mut self, policies: Policies) -> Self {
+        self.policies = policies;
+        self
+    }
+
+    /// The name a key is written to below a prefix
+    pub fn name(&self, prefix: &str, key: &str) -> String {
+        name_below(&self.transform, prefix, key)
+    }
+
+    /// Writes a single value, returning the version it was stored as
+    pub async fn put(
+        &self,
+        name: &str,
+        value: &str,
+        param_type: ParameterType,
+    ) -> Result<i64, Box<dyn Error>> {
+        self.put_tagged(name, value, param_type, &self.tags).await
+    }
+
+    /// Writes a single value with its own tags. SSM refuses tags together with overwrite, so
+    /// when overwriting they are added in a second request.
+    async fn put_tagged(
+        &self,
+        name: &str,
+        value: &str,
+        param_type: ParameterType,
+        tags: &[Tag],
+    ) -> Result<i64, Box<dyn Error>> {
+        let tier = tier::tier_for(name, self.tier, value.len(), &self.policies)?;
+        let tags = tags.iter().map(Into::into).collect::<Vec<_>>();
+
+        let resp = self
+            .client
+            .put_parameter()
+            .name(name)
+            .overwrite(self.force)
+            .set_type(Some(param_type))
+            .value(value)
+            .set_description(self.description.clone())
+            .set_tier(tier.map(Into::into))
+            .set_policies(self.policies.to_json())
+            .set_tags(Some(tags.clone()).filter(|tags| !tags.is_empty() && !self.force))
+            .send()
+            .await
+            .map_err(Box::new)?;
+
+        if self.force && !tags.is_empty() {
+            self.client
+                .add_tags_to_resource()
+                .resource_type(ResourceTypeForTagging::Parameter)
+                .resource_id(name)
+                .set_tags(Some(tags))
                 .send()
                 .await
-            {
-                Ok(_) => println!("Wrote {}/{}", bag.prefix, param.key.to_lowercase()),
-                Err(err) => println!(
-                    "Failed to write {}/{} due to {} {:?}",
-                    bag.prefix,
-                    param.key.to_lowercase(),
-                    err,
-                    err
-                ),
+                .map_err(|err| {
+                    format!(
+                        "stored version {} but failed to tag it: {}",
+                        resp.version, err
+                    )
+                })?;
+        }
+
+        Ok(resp.version)
+    }
+
+    pub async fn write(&self, bag: &ParamBag) -> WriteSummary {
+        let params = bag
+            .params
+            .iter()
+            .map(|param| {
+                (
+                    self.name(&bag.prefix, &param.key),
+                    param.value.expose(),
+                    self.param_type(&param.key),
+                    &self.tags[..],
+                )
+            })
+            .collect();
+
+        self.put_each(params).await
+    }
+
+    /// Writes parameters back under the names, types and tags of their metadata, as saved by
+    /// `export` and `rm --backup` or read by `copy`
+    pub async fn restore(&self, params: &[Param]) -> WriteSummary {
+        let params = params
+            .iter()
+            .filter_map(|param| {
+                let meta = param.meta.as_ref()?;
+                let param_type = meta.param_type.clone().unwrap_or(ParameterType::String);
+
+                Some((
+                    meta.name.clone(),
+                    param.value.expose(),
+                    param_type,
+                    &meta.tags[..],
+                ))
+            })
+            .collect();
+
+        self.put_each(params).await
+    }
+
+    async fn put_each(&self, params: Vec<(String, &str, ParameterType, &[Tag])>) -> WriteSummary {
+        let mut summary = WriteSummary::default();
+
+        for (name, value, param_type, tags) in params {
+            match self.put_tagged(&name, value, param_type, tags).await {
+                Ok(_) => {
+                    output::status(format!("Wrote {}", name));
+                    summary.written.push(name);
+                }
+                Err(err) => {
+                    output::error(format!("Failed to write {} due to {}", name, err));
+                    output::verbose(format!("{:?}", err));
+                    summary.failed.push((name, err.to_string()));
+                }
             };
 
             tokio::time::sleep(std::time::Duration::from_millis(200)).await;
         }
 
-        Some(())
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::parse_rename_map;
+
+    #[test]
+    fn test_names_single_parameters_like_write() {
+        let lower = KeyTransform::with_case(Case::Lower);
+        assert_eq!("/app/prod/db_host", full_name(&lower, "/app/prod/DB_HOST"));
+        assert_eq!("/app/db_host", full_name(&lower, "app/DB_HOST"));
+
+        let renamed = KeyTransform {
+            renames: parse_rename_map("primary_host=DB_HOST\n").unwrap(),
+            ..KeyTransform::with_case(Case::Lower)
+        }
+        .inverted();
+        assert_eq!("/app/primary_host", full_name(&renamed, "/app/DB_HOST"));
+    }
+
+    #[test]
+    fn test_names_copies_below_the_new_path() {
+        assert_eq!(
+            "/app/staging/db/host",
+            copied_name("/app/prod", "app/staging/", "/app/prod/db/host")
+        );
+        assert_eq!("/backup/app/host", copied_name("/", "/backup", "/app/host"));
+    }
+
+    #[test]
+    fn test_summarizes_as_json() {
+        let summary = WriteSummary {
+            written: vec!["/app/a".to_string()],
+            failed: vec![("/app/b".to_string(), "denied".to_string())],
+        };
+
+        assert_eq!(
+            r#"{"failed":[{"error":"denied","name":"/app/b"}],"written":["/app/a"]}"#,
+            summary.to_json().to_string()
+        );
+    }
+
+    #[test]
+    fn test_fails_when_any_write_failed() {
+        let mut summary = WriteSummary {
+            written: vec!["/app/a".to_string()],
+            failed: vec![],
+        };
+        assert_eq!(Ok(()), summary.check());
+
+        summary
+            .failed
+            .push(("/app/b".to_string(), "denied".to_string()));
+        assert_eq!(
+            Err("Failed to write 1 of 2 parameters".to_string()),
+            summary.check()
+        );
     }
 }
@@ -0,0 +1,300 @@
+use aws_sdk_ssm::model::{ParameterInlinePolicy, ParameterTier};
+use aws_smithy_types::date_time::Format;
+use serde_json::{json, Value};
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::history::Timestamp;
+use crate::output;
+
+/// The largest value the Standard tier accepts, in bytes
+const STANDARD_LIMIT: usize = 4096;
+
+/// The largest value the Advanced tier accepts, in bytes
+const ADVANCED_LIMIT: usize = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tier {
+    Standard,
+    Advanced,
+    /// Lets SSM pick Advanced only when a value or its policies need it
+    IntelligentTiering,
+}
+
+impl FromStr for Tier {
+    type Err = TierError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "standard" => Ok(Tier::Standard),
+            "advanced" => Ok(Tier::Advanced),
+            "intelligent-tiering" => Ok(Tier::IntelligentTiering),
+            _ => Err(TierError::Unknown(s.to_string())),
+        }
+    }
+}
+
+impl From<Tier> for ParameterTier {
+    fn from(tier: Tier) -> Self {
+        match tier {
+            Tier::Standard => ParameterTier::Standard,
+            Tier::Advanced => ParameterTier::Advanced,
+            Tier::IntelligentTiering => ParameterTier::IntelligentTiering,
+        }
+    }
+}
+
+impl Tier {
+    /// The tier SSM reports for a parameter, if it is one this tool knows
+    pub fn from_parameter_tier(tier: &ParameterTier) -> Option<Tier> {
+        match tier {
+            ParameterTier::Standard => Some(Tier::Standard),
+            ParameterTier::Advanced => Some(Tier::Advanced),
+            ParameterTier::IntelligentTiering => Some(Tier::IntelligentTiering),
+            _ => None,
+        }
+    }
+}
+
+/// Expiration and notification policies, which SSM only supports on the Advanced tier
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Policies {
+    pub expires_at: Option<Timestamp>,
+    /// Days before expiring to send a notification
+    pub notify_before_expiry: Option<u32>,
+    /// Days without a new version after which to send a notification
+    pub notify_unchanged_after: Option<u32>,
+}
+
+impl Policies {
+    pub fn is_empty(&self) -> bool {
+        self == &Policies::default()
+    }
+
+    /// The policies in the JSON form `PutParameter` expects, if there are any
+    pub fn to_json(&self) -> Option<String> {
+        let mut policies = vec![];
+
+        if let Some(at) = self
+            .expires_at
+            .and_then(|at| at.0.fmt(Format::DateTime).ok())
+        {
+            policies.push(policy("Expiration", json!({ "Timestamp": at })));
+        }
+
+        if let Some(days) = self.notify_before_expiry {
+            policies.push(policy(
+                "ExpirationNotification",
+                json!({ "Before": days.to_string(), "Unit": "Days" }),
+            ));
+        }
+
+        if let Some(days) = self.notify_unchanged_after {
+            policies.push(policy(
+                "NoChangeNotification",
+                json!({ "After": days.to_string(), "Unit": "Days" }),
+            ));
+        }
+
+        if policies.is_empty() {
+            None
+        } else {
+            Some(Value::Array(policies).to_string())
+        }
+    }
+
+    /// Reads back the policies SSM reports for a parameter. Policies this tool cannot write, such
+    /// as notifications counted in hours, are left out with a warning.
+    pub fn from_inline(name: &str, inline: &[ParameterInlinePolicy]) -> Policies {
+        let mut policies = Policies::default();
+
+        for text in inline.iter().filter_map(|p| p.policy_text.as_deref()) {
+            let policy: Value = serde_json::from_str(text).unwrap_or_default();
+            let attributes = &policy["Attributes"];
+            let days = |field: &str| {
+                attributes[field]
+                    .as_str()
+                    .filter(|_| attributes["Unit"] == "Days")
+                    .and_then(|days| days.parse().ok())
+            };
+
+            let known = match policy["Type"].as_str() {
+                Some("Expiration") => {
+                    policies.expires_at = attributes["Timestamp"]
+                        .as_str()
+                        .and_then(|at| at.parse().ok());
+                    policies.expires_at.is_some()
+                }
+                Some("ExpirationNotification") => {
+                    policies.notify_before_expiry = days("Before");
+                    policies.notify_before_expiry.is_some()
+                }
+                Some("NoChangeNotification") => {
+                    policies.notify_unchanged_after = days("After");
+                    policies.notify_unchanged_after.is_some()
+                }
+                _ => false,
+            };
+
+            if !known {
+                output::warning(format!("Leaving out a policy of {}: {}", name, text));
+            }
+        }
+
+        policies
+    }
+}
+
+fn policy(policy_type: &str, attributes: Value) -> Value {
+    json!({ "Type": policy_type, "Version": "1.0", "Attributes": attributes })
+}
+
+/// Picks the tier to store a value in. Without an explicit tier, values over 4KB and values with
+/// policies are promoted to Advanced, which is charged for, with a warning.
+pub fn tier_for(
+    name: &str,
+    tier: Option<Tier>,
+    size: usize,
+    policies: &Policies,
+) -> Result<Option<Tier>, TierError> {
+    if size > ADVANCED_LIMIT {
+        return Err(TierError::TooLarge {
+            name: name.to_string(),
+            size,
+        });
+    }
+
+    let needs_advanced = size > STANDARD_LIMIT || !policies.is_empty();
+
+    match tier {
+        Some(Tier::Standard) if needs_advanced => Err(TierError::NeedsAdvanced(name.to_string())),
+        None if needs_advanced => {
+            output::warning(format!(
+                "Storing {} in the Advanced tier, as it is {} bytes{}",
+                name,
+                size,
+                if policies.is_empty() {
+                    ""
+                } else {
+                    " and has policies"
+                }
+            ));
+            Ok(Some(Tier::Advanced))
+        }
+        tier => Ok(tier),
+    }
+}
+
+#[derive(Debug)]
+pub enum TierError {
+    Unknown(String),
+    TooLarge { name: String, size: usize },
+    NeedsAdvanced(String),
+}
+
+impl fmt::Display for TierError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TierError::Unknown(tier) => write!(f, "{} is not a parameter tier", tier),
+            TierError::TooLarge { name, size } => write!(
+                f,
+                "{} is {} bytes, more than the {} bytes SSM allows",
+                name, size, ADVANCED_LIMIT
+            ),
+            TierError::NeedsAdvanced(name) => write!(
+                f,
+                "{} needs the Advanced tier for values over {} bytes or with policies, pass --tier advanced",
+                name, STANDARD_LIMIT
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TierError {}
+
+#[cfg(test)]
+mod tests {
+    use aws_smithy_types::DateTime;
+
+    use super::*;
+
+    #[test]
+    fn test_promotes_large_values_to_advanced() {
+        let none = Policies::default();
+
+        assert_eq!(None, tier_for("/app/a", None, 4096, &none).unwrap());
+        assert_eq!(
+            Some(Tier::Advanced),
+            tier_for("/app/cert", None, 4097, &none).unwrap()
+        );
+        assert_eq!(
+            Some(Tier::IntelligentTiering),
+            tier_for("/app/cert", Some(Tier::IntelligentTiering), 5000, &none).unwrap()
+        );
+        assert!(tier_for("/app/cert", Some(Tier::Standard), 4097, &none).is_err());
+        assert!(tier_for("/app/cert", None, 8193, &none).is_err());
+    }
+
+    #[test]
+    fn test_promotes_values_with_policies_to_advanced() {
+        let policies = Policies {
+            notify_unchanged_after: Some(90),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Some(Tier::Advanced),
+            tier_for("/app/key", None, 10, &policies).unwrap()
+        );
+        assert!(tier_for("/app/key", Some(Tier::Standard), 10, &policies).is_err());
+    }
+
+    #[test]
+    fn test_serializes_policies() {
+        assert_eq!(None, Policies::default().to_json());
+
+        let policies = Policies {
+            expires_at: Some(Timestamp(DateTime::from_secs(1_600_000_000))),
+            notify_before_expiry: Some(15),
+            notify_unchanged_after: None,
+        };
+
+        assert_eq!(
+            r#"[{"Attributes":{"Timestamp":"2020-09-13T12:26:40Z"},"Type":"Expiration","Version":"1.0"},{"Attributes":{"Before":"15","Unit":"Days"},"Type":"ExpirationNotification","Version":"1.0"}]"#,
+            policies.to_json().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_reads_back_policies() {
+        let policies = Policies {
+            expires_at: Some(Timestamp(DateTime::from_secs(1_600_000_000))),
+            notify_before_expiry: None,
+            notify_unchanged_after: Some(90),
+        };
+        let json: Value = serde_json::from_str(&policies.to_json().unwrap()).unwrap();
+        let mut inline: Vec<_> = json
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|policy| {
+                ParameterInlinePolicy::builder()
+                    .policy_text(policy.to_string())
+                    .build()
+            })
+            .collect();
+
+        assert_eq!(policies, Policies::from_inline("/app/key", &inline));
+
+        inline.push(
+            ParameterInlinePolicy::builder()
+                .policy_text(
+                    r#"{"Type":"ExpirationNotification","Version":"1.0","Attributes":{"Before":"5","Unit":"Hours"}}"#,
+                )
+                .build(),
+        );
+
+        assert_eq!(policies, Policies::from_inline("/app/key", &inline));
+    }
+}
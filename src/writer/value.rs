@@ -0,0 +1,164 @@
+use std::fmt;
+use std::io::{self, Read};
+
+use crate::secret::Secret;
+
+/// Where `set` takes its value from
+#[derive(Debug, PartialEq)]
+pub enum ValueSource {
+    Literal(Secret),
+    /// Everything piped in, less a single trailing line break
+    Stdin,
+    /// The contents of a file, as is
+    File(String),
+    /// Asked for on the terminal without echoing it
+    Prompt,
+}
+
+impl ValueSource {
+    /// Picks the source from the arguments of `set`, where a value of `-` means stdin
+    pub fn from_args(value: Option<&str>, from_file: Option<&str>) -> Self {
+        match (value, from_file) {
+            (_, Some(file)) => ValueSource::File(file.to_string()),
+            (Some("-"), None) => ValueSource::Stdin,
+            (Some(value), None) => ValueSource::Literal(value.into()),
+            (None, None) => ValueSource::Prompt,
+        }
+    }
+
+    pub fn read(&self, name: &str) -> Result<String, ValueError> {
+        self.read_from(name, io::stdin(), |prompt| {
+            rpassword::read_password_from_tty(Some(prompt))
+        })
+    }
+
+    /// Reads the value, taking stdin and the terminal prompt as arguments
+    fn read_from<R, P>(&self, name: &str, mut stdin: R, prompt: P) -> Result<String, ValueError>
+    where
+        R: Read,
+        P: FnOnce(&str) -> io::Result<String>,
+    {
+        match self {
+            ValueSource::Literal(value) => Ok(value.expose().to_string()),
+            ValueSource::Stdin => {
+                let mut value = String::new();
+                stdin
+                    .read_to_string(&mut value)
+                    .map_err(ValueError::Stdin)?;
+
+                Ok(strip_line_break(value))
+            }
+            ValueSource::File(file) => {
+                std::fs::read_to_string(file).map_err(|source| ValueError::File {
+                    file: file.clone(),
+                    source,
+                })
+            }
+            ValueSource::Prompt => {
+                prompt(&format!("Value for {}: ", name)).map_err(ValueError::Prompt)
+            }
+        }
+    }
+}
+
+fn strip_line_break(mut value: String) -> String {
+    if value.ends_with('\n') {
+        value.pop();
+
+        if value.ends_with('\r') {
+            value.pop();
+        }
+    }
+
+    value
+}
+
+#[derive(Debug)]
+pub enum ValueError {
+    Stdin(io::Error),
+    File { file: String, source: io::Error },
+    Prompt(io::Error),
+}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValueError::Stdin(err) => write!(f, "Failed to read the value from stdin: {}", err),
+            ValueError::File { file, source } => write!(f, "Failed to read {}: {}", file, source),
+            ValueError::Prompt(err) => write!(
+                f,
+                "Failed to prompt for the value, pass it as an argument or with --from-file: {}",
+                err
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ValueError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_picks_value_source() {
+        assert_eq!(
+            ValueSource::Literal("x".into()),
+            ValueSource::from_args(Some("x"), None)
+        );
+        assert_eq!(ValueSource::Stdin, ValueSource::from_args(Some("-"), None));
+        assert_eq!(
+            ValueSource::File("cert.pem".to_string()),
+            ValueSource::from_args(None, Some("cert.pem"))
+        );
+        assert_eq!(ValueSource::Prompt, ValueSource::from_args(None, None));
+    }
+
+    fn no_prompt(_: &str) -> io::Result<String> {
+        panic!("Prompted for a value")
+    }
+
+    #[test]
+    fn test_reads_stdin_less_one_line_break() {
+        let value = ValueSource::Stdin
+            .read_from("/app/x", "line one\nline two\n\n".as_bytes(), no_prompt)
+            .unwrap();
+        assert_eq!("line one\nline two\n", value);
+
+        let value = ValueSource::Literal("given".into())
+            .read_from("/app/x", "ignored".as_bytes(), no_prompt)
+            .unwrap();
+        assert_eq!("given", value);
+    }
+
+    #[test]
+    fn test_prompts_with_the_name() {
+        let value = ValueSource::Prompt
+            .read_from("/app/db_password", io::empty(), |prompt| {
+                assert_eq!("Value for /app/db_password: ", prompt);
+                Ok("hunter2".to_string())
+            })
+            .unwrap();
+        assert_eq!("hunter2", value);
+
+        let err = ValueSource::Prompt
+            .read_from("/app/x", io::empty(), |_| {
+                Err(io::Error::new(io::ErrorKind::NotFound, "no tty"))
+            })
+            .unwrap_err();
+        assert!(matches!(err, ValueError::Prompt(_)));
+    }
+
+    #[test]
+    fn test_reads_files_as_is() {
+        let value = ValueSource::File("test_data/malformed.env".to_string())
+            .read("/app/x")
+            .unwrap();
+        assert!(value.ends_with("API_KEY=second\n"));
+
+        assert!(ValueSource::File("test_data/missing".to_string())
+            .read("/app/x")
+            .is_err());
+        assert_eq!("a\nb", strip_line_break("a\nb\r\n".to_string()));
+    }
+}